@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE track_positions (
+    track_id Integer PRIMARY KEY NOT NULL references tracks(id),
+    position Integer NOT NULL
+);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+CREATE TABLE stations (
+    id Integer PRIMARY KEY NOT NULL,
+    name VARCHAR NOT NULL,
+    url VARCHAR UNIQUE NOT NULL
+);
+
+CREATE TABLE playliststations (
+    id Integer PRIMARY KEY NOT NULL,
+    playlist_id Integer NOT NULL references playlists(id),
+    station_id Integer NOT NULL references stations(id),
+    playlist_order Integer NOT NULL,
+    UNIQUE(playlist_id, playlist_order)
+);
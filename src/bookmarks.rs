@@ -0,0 +1,197 @@
+use crate::types::DBPool;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use std::path::Path;
+use viola_common::schema::{track_positions, tracks};
+use viola_common::{BookmarkJson, Track};
+
+/// Decides in which tracks we remember the position, i.e., audiobooks and podcasts
+#[derive(Debug, Default)]
+pub(crate) struct BookmarkRules {
+    /// genres from the comma separated `bookmark_genres` preference
+    genres: Vec<String>,
+    /// directories from the comma separated `bookmark_dirs` preference
+    dirs: Vec<String>,
+    /// smart playlists that have `remember_position` set
+    playlists: Vec<String>,
+}
+
+/// splits a comma separated preference
+fn split_preference(value: Option<&String>) -> Vec<String> {
+    value.map_or_else(Vec::new, |v| {
+        v.split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    })
+}
+
+impl BookmarkRules {
+    /// reads the rules from the preferences and the smart playlists
+    pub(crate) fn load() -> Self {
+        let prefs = crate::utils::load_preferences();
+        BookmarkRules {
+            genres: split_preference(prefs.get("bookmark_genres")),
+            dirs: split_preference(prefs.get("bookmark_dirs")),
+            playlists: crate::smartplaylist_parser::construct_smartplaylists_from_config()
+                .into_iter()
+                .filter(|s| s.remember_position)
+                .map(|s| s.name)
+                .collect(),
+        }
+    }
+
+    /// do we remember the position in `track` which we play in the tab called `tab`, never for streams
+    pub(crate) fn applies(&self, track: &Track, tab: &str) -> bool {
+        if track.is_stream() {
+            return false;
+        }
+        self.genres
+            .iter()
+            .any(|g| g.eq_ignore_ascii_case(&track.genre))
+            || self
+                .dirs
+                .iter()
+                .any(|d| Path::new(&track.path).starts_with(d))
+            || self.playlists.iter().any(|p| p == tab)
+    }
+}
+
+/// remembers `position` seconds in `track`, stations have no bookmarks
+pub(crate) fn save(db: &DBPool, track: &Track, position: u64) -> Result<(), String> {
+    let id = track.library_id().ok_or_else(|| {
+        format!(
+            "Cannot remember the position in the station {}",
+            track.title
+        )
+    })?;
+    let position = i32::try_from(position).map_err(|e| e.to_string())?;
+    diesel::replace_into(track_positions::table)
+        .values((
+            track_positions::track_id.eq(id),
+            track_positions::position.eq(position),
+        ))
+        .execute(&mut *db.lock())
+        .map(|_| ())
+        .map_err(|e| format!("Could not save bookmark: {}", e))
+}
+
+/// returns the remembered position in seconds of `track`, None for a station
+pub(crate) fn get(db: &DBPool, track: &Track) -> Option<u64> {
+    track_positions::table
+        .find(track.library_id()?)
+        .select(track_positions::position)
+        .first::<i32>(&mut *db.lock())
+        .ok()
+        .and_then(|p| u64::try_from(p).ok())
+}
+
+/// forgets the position of the library track with `id`, i.e., because it finished
+pub(crate) fn remove(db: &DBPool, id: i32) -> Result<(), String> {
+    if id < 0 {
+        return Err(format!(
+            "{} is the id of a station, which has no bookmark",
+            id
+        ));
+    }
+    diesel::delete(track_positions::table.find(id))
+        .execute(&mut *db.lock())
+        .map(|_| ())
+        .map_err(|e| format!("Could not remove bookmark: {}", e))
+}
+
+/// returns all bookmarks with their tracks
+pub(crate) fn list(db: &DBPool) -> Result<Vec<BookmarkJson>, String> {
+    tracks::table
+        .inner_join(track_positions::table)
+        .select((tracks::all_columns, track_positions::position))
+        .order(tracks::path.asc())
+        .load::<(Track, i32)>(&mut *db.lock())
+        .map(|v| {
+            v.into_iter()
+                .map(|(track, position)| BookmarkJson {
+                    track,
+                    position: u64::try_from(position).unwrap_or(0),
+                })
+                .collect()
+        })
+        .map_err(|e| format!("Could not load bookmarks: {}", e))
+}
+
+/// forgets all bookmarks and returns how many there were
+pub(crate) fn clear(db: &DBPool) -> Result<usize, String> {
+    diesel::delete(track_positions::table)
+        .execute(&mut *db.lock())
+        .map_err(|e| format!("Could not clear bookmarks: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test_db;
+
+    fn track(genre: &str, path: &str) -> Track {
+        Track {
+            id: 0,
+            title: String::from("Title"),
+            artist: String::from("Artist"),
+            album: String::from("Album"),
+            genre: String::from(genre),
+            tracknumber: None,
+            year: None,
+            path: String::from(path),
+            length: 1,
+            albumpath: None,
+            playcount: None,
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = BookmarkRules {
+            genres: split_preference(Some(&String::from("Audiobook, Podcast,"))),
+            dirs: vec![String::from("/music/books/")],
+            playlists: vec![String::from("Long Mixes")],
+        };
+        assert!(rules.applies(&track("audiobook", "/music/a.mp3"), "Default"));
+        assert!(rules.applies(&track("Rock", "/music/books/a.mp3"), "Default"));
+        assert!(!rules.applies(&track("Rock", "/music/booksale/a.mp3"), "Default"));
+        assert!(rules.applies(&track("Rock", "/music/a.mp3"), "Long Mixes"));
+        assert!(!rules.applies(&track("Rock", "/music/a.mp3"), "Default"));
+        assert!(!BookmarkRules::default().applies(&track("Podcast", "/music/a.mp3"), "Default"));
+        let station = Track::from(viola_common::Station {
+            id: 1,
+            name: String::from("Podcast"),
+            url: String::from("https://example.org/live"),
+        });
+        assert!(!rules.applies(&station, "Long Mixes"));
+    }
+
+    fn library_track(id: i32) -> Track {
+        Track {
+            id,
+            ..track("Audiobook", "/music/books/a.mp3")
+        }
+    }
+
+    #[test]
+    fn test_save_list_remove() {
+        let db = test_db();
+        let first = library_track(1);
+        assert_eq!(get(&db, &first), None);
+
+        save(&db, &first, 100).unwrap();
+        save(&db, &library_track(2), 5).unwrap();
+        save(&db, &first, 200).unwrap();
+        assert_eq!(get(&db, &first), Some(200));
+        let bookmarks = list(&db).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert!(bookmarks
+            .iter()
+            .any(|b| b.track.id == 1 && b.position == 200));
+
+        remove(&db, 1).unwrap();
+        assert_eq!(get(&db, &first), None);
+        assert_eq!(clear(&db).unwrap(), 1);
+        assert!(list(&db).unwrap().is_empty());
+    }
+}
@@ -5,16 +5,18 @@ use diesel_migrations::MigrationHarness;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations};
 use indicatif::ParallelProgressIterator;
 use indicatif::{ProgressBar, ProgressStyle};
-use log::{error, info};
+use log::{error, info, warn};
+use parking_lot::Mutex;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::Deserialize;
 use std::iter::FromIterator;
 use std::ops::DerefMut;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{collections::HashSet, path::PathBuf};
 use std::{thread, time};
 use viola_common::schema::tracks;
-use viola_common::Track;
+use viola_common::{ScanMessage, Track};
 use walkdir::DirEntry;
 
 static PROGRESSBAR_STYLE: &str =
@@ -33,13 +35,18 @@ impl UpdatePlayCount for Track {
         use rand::RngCore;
         use viola_common::schema::tracks::dsl::*;
 
+        // stations are not in the tracks table
+        let Some(track_id) = self.library_id() else {
+            return;
+        };
+
         //wait a random time
         let mut rng = rand::thread_rng();
         std::thread::sleep(std::time::Duration::new(0, rng.next_u32()));
         let mut db = pool.lock();
 
         let db_track: Result<Track, diesel::result::Error> =
-            tracks.find(self.id).first(db.deref_mut());
+            tracks.find(track_id).first(db.deref_mut());
         if let Ok(mut track) = db_track {
             track.playcount = Some(1 + track.playcount.unwrap_or(0));
             if track.save_changes::<Track>(db.deref_mut()).is_err() {
@@ -76,8 +83,13 @@ pub(crate) fn setup_db_connection() -> Result<diesel::SqliteConnection, String>
         return Err(String::from("Dir does not exists"));
     }
     db_file.push("music.db");
-    SqliteConnection::establish(db_file.to_str().unwrap())
-        .map_err(|_| String::from("DB Connection error"))
+    let mut connection = SqliteConnection::establish(db_file.to_str().unwrap())
+        .map_err(|_| String::from("DB Connection error"))?;
+    // databases created by older versions miss the newer tables
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Could not migrate database: {}", e))?;
+    Ok(connection)
 }
 
 /// create the db file
@@ -95,15 +107,37 @@ pub(crate) fn create_db() {
     connection.run_pending_migrations(MIGRATIONS).unwrap();
 }
 
-/// is this a valid file, i.e., has the correct extension
-fn is_valid_file(s: &Result<DirEntry, walkdir::Error>) -> bool {
+/// the extensions we scan if nothing else is set in the preferences under `extensions`
+const DEFAULT_EXTENSIONS: [&str; 7] = ["ogg", "flac", "mp3", "wma", "aac", "opus", "m4a"];
+
+/// returns the extensions we scan, set as a comma separated list under `extensions` in the preferences
+fn valid_extensions() -> Vec<String> {
+    parse_extensions(crate::utils::load_preferences().get("extensions"))
+}
+
+/// the extensions in the comma separated list `extensions`, the default ones if there are none
+fn parse_extensions(extensions: Option<&String>) -> Vec<String> {
+    extensions
+        .map(|e| {
+            e.split(',')
+                .map(|ex| ex.trim().to_lowercase())
+                .filter(|ex| !ex.is_empty())
+                .collect::<Vec<String>>()
+        })
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_EXTENSIONS.iter().map(|ex| ex.to_string()).collect())
+}
+
+/// is this a valid file, i.e., has one of the `extensions`
+fn is_valid_file(s: &Result<DirEntry, walkdir::Error>, extensions: &[String]) -> bool {
     if let Ok(ref sp) = *s {
         if sp.metadata().unwrap().file_type().is_file() {
             Some(true)
-                == sp.path().extension().map(|ex| {
-                    ["ogg", "flac", "mp3", "wma", "aac", "opus", "m4a"]
-                        .contains(&ex.to_str().unwrap())
-                })
+                == sp
+                    .path()
+                    .extension()
+                    .and_then(|ex| ex.to_str())
+                    .map(|ex| extensions.contains(&ex.to_lowercase()))
         } else {
             false
         }
@@ -143,32 +177,98 @@ fn get_album_file(s: &str) -> Option<String> {
         .and_then(|p: PathBuf| p.to_str().map(String::from))
 }
 
-/// construct a `NewTrack` from a path pointing to a file
-fn construct_track_from_path(s: &str) -> NewTrack {
-    let taglibfile = taglib::File::new(s);
-    if let Ok(ataglib) = taglibfile {
-        let tags = ataglib
-            .tag()
-            .unwrap_or_else(|e| panic!("Could not read tags for: {}. {:?}", s, e));
-        let properties = ataglib
-            .audioproperties()
-            .unwrap_or_else(|_| panic!("Could not find audio properties for: {}", s));
-        let album = get_album_file(s);
-        //tracknumber and year return 0 if none set
-        NewTrack {
-            title: tags.title().unwrap_or_default(),
-            artist: tags.artist().unwrap_or_default(),
-            album: tags.album().unwrap_or_default(),
-            genre: tags.genre().unwrap_or_default(),
-            tracknumber: tags.track().map(|i| i as i32),
-            year: tags.year().map(|i| i as i32),
-            path: s.to_string(),
-            length: properties.length() as i32,
-            albumpath: album,
+/// construct a `NewTrack` from a path pointing to a file, uses `discoverer` if there is one and taglib cannot read the file
+fn construct_track_from_path(s: &str, discoverer: Option<&Discoverer>) -> Result<NewTrack, String> {
+    construct_track_with_taglib(s).or_else(|err| match discoverer {
+        Some(discoverer) => {
+            info!("{}, trying gstreamer", err);
+            construct_track_with_discoverer(s, discoverer)
         }
-    } else {
-        panic!("Taglib could not open file: {}", s);
+        None => Err(err),
+    })
+}
+
+/// construct a `NewTrack` with taglib
+fn construct_track_with_taglib(s: &str) -> Result<NewTrack, String> {
+    let ataglib = taglib::File::new(s).map_err(|_| format!("Taglib could not open file: {}", s))?;
+    let tags = ataglib
+        .tag()
+        .map_err(|e| format!("Could not read tags for: {}. {:?}", s, e))?;
+    let properties = ataglib
+        .audioproperties()
+        .map_err(|_| format!("Could not find audio properties for: {}", s))?;
+    let album = get_album_file(s);
+    //tracknumber and year return 0 if none set
+    Ok(NewTrack {
+        title: tags.title().unwrap_or_default(),
+        artist: tags.artist().unwrap_or_default(),
+        album: tags.album().unwrap_or_default(),
+        genre: tags.genre().unwrap_or_default(),
+        tracknumber: tags.track().map(|i| i as i32),
+        year: tags.year().map(|i| i as i32),
+        path: s.to_string(),
+        length: properties.length() as i32,
+        albumpath: album,
+    })
+}
+
+/// The gstreamer discoverer of a scan. Discovering is synchronous and not reentrant, so the threads take turns
+type Discoverer = Mutex<gstreamer_pbutils::Discoverer>;
+
+/// creates the discoverer for one scan
+fn make_discoverer() -> Result<Discoverer, String> {
+    gstreamer::init().map_err(|e| format!("Could not init gstreamer: {}", e))?;
+    gstreamer_pbutils::Discoverer::new(gstreamer::ClockTime::from_seconds(10))
+        .map(Mutex::new)
+        .map_err(|e| format!("Could not create discoverer: {}", e))
+}
+
+/// construct a `NewTrack` with the gstreamer discoverer, which reads everything playbin can play
+fn construct_track_with_discoverer(s: &str, discoverer: &Discoverer) -> Result<NewTrack, String> {
+    use gstreamer::tags::{Album, Artist, DateTime, Genre, Title, TrackNumber};
+
+    let uri = Path::new(s)
+        .canonicalize()
+        .ok()
+        .and_then(|p| gstreamer::glib::filename_to_uri(p, None).ok())
+        .ok_or_else(|| format!("Could not construct uri for: {}", s))?;
+    let info = discoverer
+        .lock()
+        .discover_uri(&uri)
+        .map_err(|e| format!("Gstreamer could not read file: {}. {}", s, e))?;
+    if info.audio_streams().is_empty() {
+        return Err(format!("No audio stream found in: {}", s));
     }
+
+    /// reads the string tag `$tag` from `$tags` or returns an empty string
+    macro_rules! tag_string {
+        ($tags: expr, $tag: ty) => {
+            $tags
+                .as_ref()
+                .and_then(|t| t.get::<$tag>())
+                .map(|v| v.get().to_string())
+                .unwrap_or_default()
+        };
+    }
+
+    let tags = info.tags();
+    Ok(NewTrack {
+        title: tag_string!(tags, Title),
+        artist: tag_string!(tags, Artist),
+        album: tag_string!(tags, Album),
+        genre: tag_string!(tags, Genre),
+        tracknumber: tags
+            .as_ref()
+            .and_then(|t| t.get::<TrackNumber>())
+            .map(|v| v.get() as i32),
+        year: tags
+            .as_ref()
+            .and_then(|t| t.get::<DateTime>())
+            .map(|v| v.get().year()),
+        path: s.to_string(),
+        length: info.duration().map_or(0, |d| d.seconds() as i32),
+        albumpath: get_album_file(s),
+    })
 }
 
 /// are the tags, not tracks equal?
@@ -184,9 +284,9 @@ fn tags_equal(nt: &NewTrack, ot: &Track) -> bool {
 }
 
 /// insert tracks but retry
-fn insert_track_with_error_retries(s: &str, db: &DBPool) -> Result<(), String> {
+fn insert_track_with_error_retries(new_track: &NewTrack, db: &DBPool) -> Result<(), String> {
     for i in 1..3 {
-        let res = insert_track(s, db);
+        let res = insert_track(new_track, db);
         if res.is_ok() {
             return res;
         } else if i > 0 {
@@ -198,28 +298,28 @@ fn insert_track_with_error_retries(s: &str, db: &DBPool) -> Result<(), String> {
     Err(String::from("Could not insert"))
 }
 
-/// insert a track into a db given by the filepath `s``
-fn insert_track(s: &str, db: &DBPool) -> Result<(), String> {
+/// insert `new_track` into a db or update the tags of the track with the same path
+fn insert_track(new_track: &NewTrack, db: &DBPool) -> Result<(), String> {
     use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
     use viola_common::schema::tracks::dsl::*;
 
-    let new_track = construct_track_from_path(s);
+    let s = &new_track.path;
     let old_track_perhaps = tracks
         .filter(path.eq(&new_track.path))
         .get_result::<Track>(&mut *db.lock());
 
     if let Ok(mut old_track) = old_track_perhaps {
-        if tags_equal(&new_track, &old_track) {
+        if tags_equal(new_track, &old_track) {
             Ok(())
         } else {
-            old_track.title = new_track.title;
-            old_track.artist = new_track.artist;
-            old_track.album = new_track.album;
-            old_track.genre = new_track.genre;
+            old_track.title = new_track.title.clone();
+            old_track.artist = new_track.artist.clone();
+            old_track.album = new_track.album.clone();
+            old_track.genre = new_track.genre.clone();
             old_track.tracknumber = new_track.tracknumber;
             old_track.year = new_track.year;
             old_track.length = new_track.length;
-            old_track.albumpath = new_track.albumpath;
+            old_track.albumpath = new_track.albumpath.clone();
 
             old_track
                 .save_changes::<Track>(&mut *db.lock())
@@ -228,34 +328,56 @@ fn insert_track(s: &str, db: &DBPool) -> Result<(), String> {
         }
     } else {
         diesel::insert_into(tracks)
-            .values(&new_track)
+            .values(new_track)
             .execute(&mut *db.lock())
             .map(|_| ())
             .map_err(|err| format!("Insertion Error for track {}, See full: {:?}", s, err))
     }
 }
 
+/// how many processed files we wait before reporting progress
+const PROGRESS_REPORT_STEP: usize = 100;
+
 /// Tested on 01-06-2019 with jwalk and walkdir. walkdir was faster on my machine
-pub(crate) fn build_db(p: &str, db: &DBPool, fast_delete: bool) -> Result<(), String> {
+/// `progress` gets called with the progress of the scan, i.e., to show it in the web gui
+pub(crate) fn build_db(
+    p: &str,
+    db: &DBPool,
+    fast_delete: bool,
+    progress: &(dyn Fn(ScanMessage) + Sync),
+) -> Result<(), String> {
     info!("Building database, getting walkdir iterator");
+    // an unmounted drive would look like an empty library and we would delete every track
+    if !Path::new(p).is_dir() {
+        return Err(format!(
+            "{} is not a directory, is the drive mounted? Not touching the library",
+            p
+        ));
+    }
     let pb = ProgressBar::new_spinner();
     let style = ProgressStyle::default_spinner()
         .template(PROGRESSBAR_UNKNOWN_STYLE)
         .map_err(|_| String::from("Error in progressstyle"))?;
     pb.set_style(style);
     pb.set_message("Collecting files");
+    let extensions = valid_extensions();
+    // taglib reads most files on its own, so we scan without the fallback
+    let discoverer = make_discoverer()
+        .map_err(|e| warn!("{}, only reading files with taglib", e))
+        .ok();
     let files = pb
         .wrap_iter(
             walkdir::WalkDir::new(p)
                 .into_iter()
                 .filter_entry(|e| !is_hidden(e)),
         )
-        .filter(is_valid_file)
+        .filter(|e| is_valid_file(e, &extensions))
         .map(|i| String::from(i.unwrap().path().to_str().unwrap()))
         .collect::<HashSet<String>>();
     pb.finish_with_message("Done Updating");
 
     let file_count = files.len();
+    progress(ScanMessage::FilesFound(file_count));
 
     {
         use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl, TextExpressionMethods};
@@ -264,15 +386,21 @@ pub(crate) fn build_db(p: &str, db: &DBPool, fast_delete: bool) -> Result<(), St
             tracks
                 .select(path)
                 .load(&mut *db.lock())
-                .expect("Error in loading old files")
+                .map_err(|err| format!("Error in loading old files, See full: {:?}", err))?
         } else {
             tracks
                 .select(path)
                 //ignore files that are not in the path
                 .filter(path.like(String::from("%") + p + "%"))
                 .load(&mut *db.lock())
-                .expect("Error in loading old files")
+                .map_err(|err| format!("Error in loading old files, See full: {:?}", err))?
         });
+        if files.is_empty() && !old_files.is_empty() {
+            return Err(format!(
+                "Found no files in {}, is the drive mounted? Not touching the library",
+                p
+            ));
+        }
 
         {
             let pb = ProgressBar::new(file_count as u64);
@@ -281,16 +409,31 @@ pub(crate) fn build_db(p: &str, db: &DBPool, fast_delete: bool) -> Result<(), St
                 .template(PROGRESSBAR_STYLE)
                 .map_err(|_| String::from("Error in progressstyle"))?;
             pb.set_style(style);
+            let processed = AtomicUsize::new(0);
             let res = files
                 .par_iter()
                 .progress_with(pb)
-                .map(|s| insert_track_with_error_retries(s, db))
+                .map(|s| {
+                    let res = match construct_track_from_path(s, discoverer.as_ref()) {
+                        Ok(new_track) => insert_track_with_error_retries(&new_track, db),
+                        Err(err) => {
+                            error!("Skipping file: {}", err);
+                            progress(ScanMessage::Error(err));
+                            Ok(())
+                        }
+                    };
+                    let count = processed.fetch_add(1, Ordering::Relaxed) + 1;
+                    if count % PROGRESS_REPORT_STEP == 0 || count == file_count {
+                        progress(ScanMessage::Processed(count, file_count));
+                    }
+                    res
+                })
                 .collect::<Result<(), String>>();
 
             if let Err(err) = res {
                 error!("Error in updating database");
                 error!("{}", err);
-                panic!("Aborting");
+                return Err(err);
             }
         }
 
@@ -312,17 +455,95 @@ pub(crate) fn build_db(p: &str, db: &DBPool, fast_delete: bool) -> Result<(), St
                 diesel::delete(tracks)
                     .filter(path.eq(i))
                     .execute(&mut *db.lock())
-                    .unwrap_or_else(|_| {
-                        panic!("Error in deleting outdated database entries: {}", &i)
-                    });
+                    .map_err(|err| {
+                        format!(
+                            "Error in deleting outdated database entry {}, See full: {:?}",
+                            i, err
+                        )
+                    })?;
             }
             pb.finish_with_message("Done removing old entries");
+            progress(ScanMessage::Removed(to_delete.len()));
         }
     }
 
     Ok(())
 }
 
+/// how many tracks we check for existence before relocating
+const RELOCATE_SAMPLE_SIZE: usize = 20;
+
+/// replaces `old_prefix` with `new_prefix` in `s`, returns None if `s` does not start with `old_prefix`.
+/// The prefix has to end at a path separator, so `/mnt/music` does not match `/mnt/music2/a.mp3`
+pub(crate) fn relocate_path(s: &str, old_prefix: &str, new_prefix: &str) -> Option<String> {
+    s.strip_prefix(old_prefix)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/') || old_prefix.ends_with('/'))
+        .map(|rest| String::from(new_prefix) + rest)
+}
+
+/// Moves all paths starting with `old_prefix` to `new_prefix` without rescanning, which keeps the playcounts.
+/// Checks that a sample of the files exists at the new location first. Returns the number of moved tracks.
+pub(crate) fn relocate(old_prefix: &str, new_prefix: &str, db: &DBPool) -> Result<usize, String> {
+    use diesel::{QueryDsl, RunQueryDsl};
+    use viola_common::schema::tracks::dsl::*;
+
+    let relocated: Vec<String> = tracks
+        .select(path)
+        .load::<String>(&mut *db.lock())
+        .map_err(|err| format!("Error in loading paths, See full: {:?}", err))?
+        .iter()
+        .filter_map(|p| relocate_path(p, old_prefix, new_prefix))
+        .collect();
+    if relocated.is_empty() {
+        return Err(format!("No track starts with {}", old_prefix));
+    }
+
+    let step = std::cmp::max(1, relocated.len() / RELOCATE_SAMPLE_SIZE);
+    if let Some(missing) = relocated
+        .iter()
+        .step_by(step)
+        .find(|p| !Path::new(p).exists())
+    {
+        return Err(format!(
+            "Could not find {} at the new location, nothing was changed",
+            missing
+        ));
+    }
+
+    relocate_paths(old_prefix, new_prefix, db)
+}
+
+/// rewrites the track paths and album paths in one transaction
+fn relocate_paths(old_prefix: &str, new_prefix: &str, db: &DBPool) -> Result<usize, String> {
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+    use viola_common::schema::tracks::dsl::*;
+
+    let mut db = db.lock();
+    db.transaction::<_, diesel::result::Error, _>(|conn| {
+        let mut count = 0;
+        for t in tracks.load::<Track>(conn)? {
+            if let Some(new_path) = relocate_path(&t.path, old_prefix, new_prefix) {
+                let new_albumpath = t
+                    .albumpath
+                    .as_deref()
+                    .and_then(|a| relocate_path(a, old_prefix, new_prefix))
+                    .or(t.albumpath);
+                diesel::update(tracks.find(t.id))
+                    .set((path.eq(new_path), albumpath.eq(new_albumpath)))
+                    .execute(conn)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    })
+    .map_err(|err| {
+        format!(
+            "Error in relocating, nothing was changed. See full: {:?}",
+            err
+        )
+    })
+}
+
 /// returns an id for a newly created playlist. Returns 0 if no playlists yet in db
 #[must_use]
 pub(crate) fn get_new_playlist_id(db: &DBPool) -> i32 {
@@ -336,3 +557,114 @@ pub(crate) fn get_new_playlist_id(db: &DBPool) -> i32 {
         .and_then(|v: Vec<i32>| v.first().copied())
         .map_or(0, |i| i + 1)
 }
+
+/// an in memory database with the tracks of `tests/tracks.toml`
+#[cfg(test)]
+pub(crate) fn test_db() -> DBPool {
+    use diesel::RunQueryDsl;
+
+    #[derive(Deserialize)]
+    struct Obj {
+        newtracks: Vec<NewTrack>,
+    }
+
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    conn.run_pending_migrations(MIGRATIONS).unwrap();
+    let string = std::fs::read_to_string("tests/tracks.toml").unwrap();
+    let val = toml::from_str::<Obj>(&string).expect("Could not parse");
+    diesel::insert_into(tracks::table)
+        .values(&val.newtracks)
+        .execute(&mut conn)
+        .unwrap();
+    std::sync::Arc::new(Mutex::new(conn))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use diesel::{QueryDsl, RunQueryDsl};
+
+    #[test]
+    fn test_relocate_paths() {
+        let db = test_db();
+        let count = relocate_paths("foo/Apo/", "/mnt/nas/Apo/", &db).unwrap();
+        let paths: Vec<String> = tracks::table
+            .select(tracks::path)
+            .load(&mut *db.lock())
+            .unwrap();
+
+        assert_eq!(count, 9);
+        assert!(paths.contains(&String::from("/mnt/nas/Apo/1/1.mp3")));
+        assert!(paths.contains(&String::from("foo/2Cel/1/3.mp3")));
+        assert!(!paths.iter().any(|p| p.starts_with("foo/Apo/")));
+    }
+
+    #[test]
+    fn test_relocate_path_components() {
+        assert_eq!(
+            relocate_path("/mnt/music/a.mp3", "/mnt/music", "/nas"),
+            Some(String::from("/nas/a.mp3"))
+        );
+        assert_eq!(
+            relocate_path("/mnt/music", "/mnt/music", "/nas"),
+            Some(String::from("/nas"))
+        );
+        assert_eq!(
+            relocate_path("/mnt/music/a.mp3", "/mnt/music/", "/nas/"),
+            Some(String::from("/nas/a.mp3"))
+        );
+        assert_eq!(
+            relocate_path("/mnt/music2/a.mp3", "/mnt/music", "/nas"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_valid_extensions() {
+        let dir = std::env::temp_dir().join(format!("viola-extensions-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.mp3", "b.OGG", "c.flac", "d.txt", "e"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        let extensions = parse_extensions(Some(&String::from(" MP3, ogg,,")));
+        assert_eq!(extensions, vec![String::from("mp3"), String::from("ogg")]);
+        let mut found: Vec<String> = walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter(|e| is_valid_file(e, &extensions))
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![String::from("a.mp3"), String::from("b.OGG")]);
+
+        assert_eq!(
+            parse_extensions(None),
+            parse_extensions(Some(&String::from(" , ")))
+        );
+        assert!(parse_extensions(None).contains(&String::from("flac")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_discoverer_fallback() {
+        let dir = std::env::temp_dir().join(format!("viola-discoverer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // taglib goes by the extension and finds no Ogg stream in the wav
+        let path = dir.join("tone.opus");
+        crate::mix::write_tone(&path);
+        let s = path.to_str().unwrap();
+
+        assert!(construct_track_with_taglib(s).is_err());
+        assert!(construct_track_from_path(s, None).is_err());
+        let discoverer = make_discoverer().unwrap();
+        let track = construct_track_from_path(s, Some(&discoverer)).unwrap();
+        assert_eq!(track.path, s);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_relocate_missing_files() {
+        let db = test_db();
+        assert!(relocate("foo/", "/this/does/not/exist/", &db).is_err());
+        assert!(relocate("bar/", "foo/", &db).is_err());
+    }
+}
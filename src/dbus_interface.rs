@@ -1,9 +1,14 @@
-use log::info;
+use log::{info, warn};
 use std::{collections::HashMap, sync::Arc};
 
-use crate::{gstreamer_wrapper::GStreamer, loaded_playlist::LoadedPlaylistExt, playlist_tabs::LoadedPlaylistExtImut, types::*};
-use viola_common::{GStreamerAction, GStreamerMessage};
-use zbus::{dbus_interface, interface, ConnectionBuilder};
+use crate::{gstreamer_wrapper::{GStreamer, MAX_RATE, MIN_RATE}, loaded_playlist::LoadedPlaylistExt, playlist_tabs::{LoadedPlaylistExtImut, PlaylistTabsExt}, types::*};
+use viola_common::{GStreamerAction, GStreamerMessage, PlayMode, RepeatMode};
+use zbus::{
+    dbus_interface, interface,
+    object_server::SignalContext,
+    zvariant::{ObjectPath, OwnedObjectPath},
+    ConnectionBuilder,
+};
 
 struct BaseInterface {}
 
@@ -59,6 +64,15 @@ impl BaseInterface {
     }
 }
 
+/// the MPRIS track id of `track`
+fn track_id(track: &viola_common::Track) -> OwnedObjectPath {
+    let path = match track.station_id() {
+        Some(id) => format!("/org/viola/station/{}", id),
+        None => format!("/org/viola/track/{}", track.id),
+    };
+    OwnedObjectPath::try_from(path).expect("Track ids are valid object paths")
+}
+
 struct PlayerInterface {
     gstreamer: Arc<GStreamer>,
     playlisttabs: PlaylistTabsPtr,
@@ -74,23 +88,80 @@ impl PlayerInterface {
 
     #[zbus(property)]
     async fn loop_status(&self) -> String {
-        "None".to_string()
+        match self.gstreamer.get_repeat() {
+            RepeatMode::Off => "None",
+            RepeatMode::Track => "Track",
+            RepeatMode::Playlist => "Playlist",
+        }
+        .to_string()
+    }
+
+    #[zbus(property)]
+    async fn set_loop_status(&self, status: String) -> zbus::fdo::Result<()> {
+        let mode = match status.as_str() {
+            "None" => RepeatMode::Off,
+            "Track" => RepeatMode::Track,
+            "Playlist" => RepeatMode::Playlist,
+            _ => {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "Unknown loop status {}",
+                    status
+                )))
+            }
+        };
+        self.gstreamer.do_gstreamer_action(GStreamerAction::SetRepeat(mode));
+        Ok(())
+    }
+
+    #[zbus(property)]
+    async fn shuffle(&self) -> bool {
+        self.gstreamer.get_play_mode() != PlayMode::Ordered
+    }
+
+    #[zbus(property)]
+    async fn set_shuffle(&self, shuffle: bool) {
+        let current = self.gstreamer.get_play_mode();
+        let mode = match (shuffle, current) {
+            // album shuffle is a shuffle too, so we keep it
+            (true, PlayMode::Ordered) => PlayMode::Shuffle,
+            (true, _) => current,
+            (false, _) => PlayMode::Ordered,
+        };
+        self.gstreamer
+            .do_gstreamer_action(GStreamerAction::SetPlayMode(mode));
     }
 
     #[zbus(property)]
     async fn rate(&self) -> f64 {
-        1.0
+        self.gstreamer.get_rate()
+    }
+
+    #[zbus(property)]
+    async fn set_rate(&self, rate: f64) {
+        // the specification says a rate of zero should pause
+        if rate == 0.0 {
+            self.gstreamer.do_gstreamer_action(GStreamerAction::Pausing);
+        } else {
+            self.gstreamer.set_rate(rate);
+        }
     }
 
     #[zbus(property)]
     async fn metadata(&self) -> HashMap<&str, zbus::zvariant::Value> {
         info!("dbus metadata");
         if self.gstreamer.get_state() == GStreamerMessage::Playing {
-            let track = self.playlisttabs.get_current_track();
-            let length = 1_000_000 * track.length;
+            let mut track = self.playlisttabs.get_current_track();
+            if track.is_stream() {
+                // a station announces what it plays, so the station name becomes the album
+                if let Some(title) = self.gstreamer.get_stream_title() {
+                    track.album = std::mem::replace(&mut track.title, title);
+                }
+            }
+            let length = 1_000_000 * i64::from(track.length);
+            let trackid = track_id(&track);
             let albumpath = track.albumpath.unwrap_or_default();
             HashMap::from([
-                ("xesam:trackid", "/track".into()),
+                ("mpris:trackid", trackid.into()),
                 ("xesam:artist", track.artist.into()),
                 ("xesam:album", track.album.into()),
                 ("xesam:title", track.title.into()),
@@ -105,22 +176,28 @@ impl PlayerInterface {
 
     #[zbus(property)]
     async fn volume(&self) -> f64 {
-        1.0
+        f64::from(self.gstreamer.get_volume().volume) / 100.0
+    }
+
+    #[zbus(property)]
+    async fn set_volume(&self, volume: f64) {
+        let percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u32;
+        self.gstreamer.do_gstreamer_action(GStreamerAction::SetVolume(percent));
     }
 
     #[zbus(property)]
     async fn position(&self) -> i64 {
-        1_000_000 * self.gstreamer.get_elapsed().unwrap_or(0) as i64
+        self.gstreamer.get_position().unwrap_or(0) as i64
     }
 
     #[zbus(property)]
     async fn minimum_rate(&self) -> f64 {
-        1.0
+        MIN_RATE
     }
 
     #[zbus(property)]
     async fn maximum_rate(&self) -> f64 {
-        1.0
+        MAX_RATE
     }
 
     #[zbus(property)]
@@ -145,7 +222,7 @@ impl PlayerInterface {
 
     #[zbus(property)]
     async fn can_seek(&self) -> bool {
-        false
+        true
     }
 
     #[zbus(property)]
@@ -219,22 +296,35 @@ impl PlayerInterface {
         Ok(())
     }
 
-    async fn seek(&self, _position: i32) -> zbus::fdo::Result<()> {
-        todo!("NYI");
-        //Not Implemented
+    async fn seek(&self, offset: i64) -> zbus::fdo::Result<()> {
+        self.gstreamer
+            .do_gstreamer_action(GStreamerAction::SeekBy(offset));
         Ok(())
     }
 
-    async fn set_position(&self, _track_id: String, _position: i32) -> zbus::fdo::Result<()> {
-        //Not Implemented
-        todo!("NYI");
+    async fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> zbus::fdo::Result<()> {
+        let Some((_, track)) = self.playlisttabs.playing() else {
+            return Ok(());
+        };
+        // the specification says we ignore positions outside of the track and old track ids
+        if track_id.as_str() != self::track_id(&track).as_str()
+            || !(0..=1_000_000 * i64::from(track.length)).contains(&position)
+        {
+            return Ok(());
+        }
+        self.gstreamer
+            .do_gstreamer_action(GStreamerAction::Seek(position as u64));
         Ok(())
     }
 
     async fn open_uri(&self, _s: String) -> zbus::fdo::Result<()> {
-        todo!("NYI");
-        Ok(())
+        Err(zbus::fdo::Error::NotSupported(String::from(
+            "Viola only plays tracks from its playlists",
+        )))
     }
+
+    #[zbus(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
 }
 
 pub(crate) async fn main(
@@ -261,50 +351,45 @@ pub(crate) async fn main(
         .await
         .expect("Error in creating connection");
 
-    //{
-    //tokio::task::spawn(async move {
-    std::future::pending::<()>().await;
-    /*
-    info!("doing signal");
     let iface_ref = conn
         .object_server()
         .interface::<_, PlayerInterface>("/org/mpris/MediaPlayer2")
         .await
-        .unwrap();
-    let iface = iface_ref.get_mut().await;
+        .map_err(|e| format!("Could not get player interface: {}", e))?;
     let mut bus = bus;
-    info!("we did all the setup and wait for the bus");
     loop {
-        tokio::select! {
-            Ok(val) = bus.recv() => {
-                info!("Found msg val {:?}", val);
-                match val {
-                    GStreamerMessage::Playing
-                    | GStreamerMessage::Pausing
-                    | GStreamerMessage::Stopped
-                    | GStreamerMessage::FileNotFound => {
-                        iface
-                            .metadata_changed(iface_ref.signal_context())
-                            .await
-                            .unwrap();
-                        iface
-                            .playback_status_changed(iface_ref.signal_context())
-                            .await
-                            .unwrap();
-                    }
-                    GStreamerMessage::ChangedDuration(_) => {
-                        /*
-                            iface
-                            .position_changed(iface_ref.signal_context())
-                            .await
-                            .unwrap();
-                        */
-                    }
-                    GStreamerMessage::Nop | GStreamerMessage::IncreasePlayCount(_) => {}
-                }
-        },
+        let val = match bus.recv().await {
+            Ok(val) => val,
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
         };
+        // we only hold the interface while signalling, otherwise property reads would wait on us
+        let iface = iface_ref.get().await;
+        let ctxt = iface_ref.signal_context();
+        let res = match val {
+            GStreamerMessage::Playing
+            | GStreamerMessage::Pausing
+            | GStreamerMessage::Stopped
+            | GStreamerMessage::FileNotFound => {
+                let res = iface.metadata_changed(ctxt).await;
+                res.and(iface.playback_status_changed(ctxt).await)
+            }
+            GStreamerMessage::VolumeChanged(_) => iface.volume_changed(ctxt).await,
+            GStreamerMessage::RateChanged => iface.rate_changed(ctxt).await,
+            GStreamerMessage::RepeatChanged(_) => iface.loop_status_changed(ctxt).await,
+            GStreamerMessage::PlayModeChanged(_) => iface.shuffle_changed(ctxt).await,
+            GStreamerMessage::Seeked(position) => {
+                PlayerInterface::seeked(ctxt, position as i64).await
+            }
+            GStreamerMessage::StreamTitleChanged => iface.metadata_changed(ctxt).await,
+            GStreamerMessage::Nop
+            | GStreamerMessage::IncreasePlayCount(_)
+            | GStreamerMessage::PlaybackError
+            | GStreamerMessage::ChangedDuration(_) => Ok(()),
+        };
+        if let Err(e) = res {
+            warn!("Could not send dbus signal: {}", e);
+        }
     }
-    */
     Ok(())
 }
@@ -0,0 +1,230 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use viola_common::EqualizerPreset;
+
+/// number of bands of the equalizer-10bands element
+pub(crate) const BANDS: usize = 10;
+/// lowest gain in dB equalizer-10bands allows
+const MIN_GAIN: f64 = -24.0;
+/// highest gain in dB equalizer-10bands allows
+const MAX_GAIN: f64 = 12.0;
+/// the file in the config dir that holds the presets of the user
+const PRESET_FILE: &str = "equalizer.toml";
+
+/// the presets we always have, the user cannot overwrite them
+const BUILTIN_PRESETS: [(&str, [f64; BANDS]); 6] = [
+    ("Flat", [0.0; BANDS]),
+    (
+        "Bass Boost",
+        [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    ),
+    (
+        "Treble Boost",
+        [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 5.0, 6.0],
+    ),
+    (
+        "Loudness",
+        [5.0, 4.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0, 4.0, 5.0],
+    ),
+    (
+        "Vocal",
+        [-2.0, -1.0, 0.0, 2.0, 4.0, 4.0, 3.0, 1.0, 0.0, -1.0],
+    ),
+    (
+        "Classical",
+        [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -3.0, -3.0, -3.0, -5.0],
+    ),
+];
+
+/// The layout of the preset file
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct PresetFile {
+    #[serde(default)]
+    preset: Vec<EqualizerPreset>,
+}
+
+fn preset_file() -> Result<std::path::PathBuf, String> {
+    crate::utils::get_config_dir().map(|p| p.join(PRESET_FILE))
+}
+
+/// reads the presets the user saved, returns an empty vector if there are none
+fn custom_presets() -> Vec<EqualizerPreset> {
+    preset_file()
+        .map(|path| read_presets(&path))
+        .unwrap_or_default()
+}
+
+/// reads the presets in the preset file at `path`, returns an empty vector if there are none
+fn read_presets(path: &Path) -> Vec<EqualizerPreset> {
+    let Ok(string) = fs::read_to_string(path) else {
+        return vec![];
+    };
+    match toml::from_str::<PresetFile>(&string) {
+        Ok(file) => file.preset,
+        Err(e) => {
+            warn!("Could not parse equalizer presets: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// clamps all gains to the range the equalizer allows
+pub(crate) fn clamp_gains(gains: [f64; BANDS]) -> [f64; BANDS] {
+    gains.map(|g| g.clamp(MIN_GAIN, MAX_GAIN))
+}
+
+/// all presets, the builtin ones first
+pub(crate) fn presets() -> Vec<EqualizerPreset> {
+    BUILTIN_PRESETS
+        .iter()
+        .map(|(name, gains)| EqualizerPreset {
+            name: String::from(*name),
+            gains: *gains,
+        })
+        .chain(custom_presets())
+        .collect()
+}
+
+/// finds the preset with `name`
+pub(crate) fn find(name: &str) -> Option<EqualizerPreset> {
+    presets().into_iter().find(|p| p.name == name)
+}
+
+/// is `name` one of the builtin presets
+fn is_builtin(name: &str) -> bool {
+    BUILTIN_PRESETS.iter().any(|(builtin, _)| *builtin == name)
+}
+
+/// replaces the preset file at `path` with `presets`
+fn write_presets(path: &Path, presets: Vec<EqualizerPreset>) -> Result<(), String> {
+    let string = toml::to_string(&PresetFile { preset: presets })
+        .map_err(|e| format!("Could not serialize presets: {}", e))?;
+    crate::utils::write_file(path, string.as_bytes())
+}
+
+/// saves `preset` into the preset file, replacing a saved preset with the same name
+pub(crate) fn save(preset: EqualizerPreset) -> Result<(), String> {
+    save_in(&preset_file()?, preset)
+}
+
+/// saves `preset` into the preset file at `path`, see `save`
+fn save_in(path: &Path, preset: EqualizerPreset) -> Result<(), String> {
+    if preset.name.is_empty() {
+        return Err(String::from("Preset needs a name"));
+    }
+    if is_builtin(&preset.name) {
+        return Err(format!("Cannot overwrite builtin preset {}", preset.name));
+    }
+    let mut presets = read_presets(path);
+    presets.retain(|p| p.name != preset.name);
+    presets.push(EqualizerPreset {
+        gains: clamp_gains(preset.gains),
+        ..preset
+    });
+    write_presets(path, presets)
+}
+
+/// deletes the saved preset called `name` from the preset file
+pub(crate) fn delete(name: &str) -> Result<(), String> {
+    delete_in(&preset_file()?, name)
+}
+
+/// deletes the preset called `name` from the preset file at `path`, see `delete`
+fn delete_in(path: &Path, name: &str) -> Result<(), String> {
+    if is_builtin(name) {
+        return Err(format!("Cannot delete builtin preset {}", name));
+    }
+    let mut presets = read_presets(path);
+    let count = presets.len();
+    presets.retain(|p| p.name != name);
+    if presets.len() == count {
+        return Err(format!("Could not find preset {}", name));
+    }
+    write_presets(path, presets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn preset(name: &str, gain: f64) -> EqualizerPreset {
+        EqualizerPreset {
+            name: String::from(name),
+            gains: [gain; BANDS],
+        }
+    }
+
+    /// a preset file in its own temporary dir, `name` keeps the tests apart
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("viola-equalizer-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join(PRESET_FILE)
+    }
+
+    #[test]
+    fn test_clamp_gains() {
+        let mut gains = [0.0; BANDS];
+        gains[0] = -30.0;
+        gains[1] = 20.0;
+        gains[2] = 3.5;
+        let clamped = clamp_gains(gains);
+        assert_eq!(clamped[0], MIN_GAIN);
+        assert_eq!(clamped[1], MAX_GAIN);
+        assert_eq!(clamped[2], 3.5);
+        assert_eq!(clamped[3], 0.0);
+    }
+
+    #[test]
+    fn test_save_and_overwrite() {
+        let path = temp_file("save");
+        save_in(&path, preset("Mine", 2.0)).unwrap();
+        save_in(&path, preset("Other", 1.0)).unwrap();
+        save_in(&path, preset("Mine", 40.0)).unwrap();
+        let presets = read_presets(&path);
+        assert_eq!(presets.len(), 2);
+        assert_eq!(presets[0].name, "Other");
+        assert_eq!(presets[1].name, "Mine");
+        assert_eq!(presets[1].gains, [MAX_GAIN; BANDS]);
+        assert!(save_in(&path, preset("", 0.0)).is_err());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_builtin_presets() {
+        let path = temp_file("builtin");
+        assert!(save_in(&path, preset("Flat", 3.0)).is_err());
+        assert!(delete_in(&path, "Bass Boost").is_err());
+        assert!(!path.exists());
+
+        save_in(&path, preset("Mine", 2.0)).unwrap();
+        assert!(delete_in(&path, "Missing").is_err());
+        delete_in(&path, "Mine").unwrap();
+        assert!(read_presets(&path).is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_file("round-trip");
+        let mut gains = [0.0; BANDS];
+        gains[4] = -2.5;
+        gains[9] = 6.25;
+        save_in(
+            &path,
+            EqualizerPreset {
+                name: String::from("Round Trip"),
+                gains,
+            },
+        )
+        .unwrap();
+        assert!(!path.with_extension("tmp").exists());
+        let presets = read_presets(&path);
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].name, "Round Trip");
+        assert_eq!(presets[0].gains, gains);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,19 +1,95 @@
 use crate::playlist_tabs::{LoadedPlaylistExtImut, PlaylistControlsImut, PlaylistTabsExt};
 use gstreamer::prelude::*;
 use log::{info, warn};
-use parking_lot::RwLock;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use parking_lot::Mutex;
+use preferences::PreferencesMap;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
 use crate::loaded_playlist::{LoadedPlaylistExt, PlaylistControls};
 //use crate::playlist_tabs::PlaylistControlsImmutable;
+use crate::bookmarks::{self, BookmarkRules};
+use crate::equalizer;
+use crate::live_stream::LiveStream;
+use crate::output;
+use crate::play_order::PlayOrder;
 use crate::types::*;
-use viola_common::{GStreamerAction, GStreamerMessage};
+use viola_common::{
+    AudioOutput, EqualizerJson, EqualizerPreset, GStreamerAction, GStreamerMessage, MixFormat,
+    PlayMode, PlaybackErrorJson, RepeatMode, ReplayGainJson, ReplayGainMode, SleepTimer,
+    SleepTimerJson, VisualizationJson, VolumeJson,
+};
+
+/// the volume we start with
+const DEFAULT_VOLUME: f64 = 0.5;
+/// how often we change the volume while crossfading
+const CROSSFADE_STEP: Duration = Duration::from_millis(50);
+/// how often we check if we have to start a crossfade or restore the playback rate
+const PLAYBACK_CHECK: Duration = Duration::from_millis(100);
+/// how many tracks in a row may fail before we stop skipping to the next one
+const MAX_CONSECUTIVE_FAILURES: usize = 5;
+/// how often we save the position in the playing track, so we can resume it after a restart
+const RESUME_SAVE: Duration = Duration::from_secs(10);
+/// the file in the config dir we save the resume position in, it changes too often for the preferences
+const RESUME_FILE: &str = "resume.json";
+/// how long the sleep timer fades out the volume before it stops
+const SLEEP_FADE: Duration = Duration::from_secs(30);
+/// the longest sleep timer in minutes we take
+const MAX_SLEEP_MINUTES: u64 = 24 * 60;
+/// slowest playback rate we allow
+pub(crate) const MIN_RATE: f64 = 0.5;
+/// fastest playback rate we allow
+pub(crate) const MAX_RATE: f64 = 3.0;
+/// how many bands the spectrum of the visualization has
+const SPECTRUM_BANDS: u32 = 32;
+/// the spectrum cuts off magnitudes below this in dB
+const SPECTRUM_THRESHOLD: i32 = -80;
+/// shortest interval of the visualization data in milliseconds
+pub(crate) const MIN_VISUALIZATION_INTERVAL: u64 = 20;
+/// the format the players hand to the mixer, it has to be the same for both
+const MIXER_CAPS: &str = "audio/x-raw,format=F32LE,rate=48000,channels=2,layout=interleaved";
+/// how long the mixer waits for the audio of the players
+const MIXER_LATENCY: gstreamer::ClockTime = gstreamer::ClockTime::from_mseconds(200);
+/// how long we wait for the output to play out before we stop it anyway
+const OUTPUT_DRAIN: Duration = Duration::from_secs(5);
+
+/// An EOS on its way through the output, the output bus thread stops the output once the sink got it
+struct Drain {
+    /// when we stop the output even if the EOS did not arrive
+    deadline: Instant,
+    /// the sink we switch to once the old one played out
+    sink: Option<gstreamer::Element>,
+}
+
+/// Where we were in the playing track, saved as json in `RESUME_FILE` to resume it after a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ResumePosition {
+    /// the tab we played in
+    tab: usize,
+    /// the position of the track in the tab
+    position: usize,
+    /// the path of the track, so we do not resume in a different track if the playlist changed
+    path: PathBuf,
+    /// seconds we played of the track
+    elapsed: u64,
+}
 
 /// Main struct to keep gstreamer
 pub(crate) struct GStreamer {
-    /// main gstreamer elements
-    element: gstreamer::Element,
+    /// main gstreamer elements, the active one plays the current track while the other one fades out when we crossfade.
+    /// Both hand their audio to `output_pipeline`.
+    players: [gstreamer::Element; 2],
+    /// mixes the audio of both players and plays it to our output, there is only one sink
+    output_pipeline: gstreamer::Pipeline,
+    /// the mixer pads the players feed in the order of `players`, we crossfade with their volume
+    mixer_pads: [gstreamer::Pad; 2],
+    /// the EOS on its way through `output_pipeline` before we stop it or switch its sink
+    drain: Mutex<Option<Drain>>,
+    /// index into `players` of the one playing the current track
+    active: AtomicUsize,
     /// current playlist tabs pointer
     current_playlist: PlaylistTabsPtr,
     /// Handles gstreamer changes to the gui
@@ -23,14 +99,405 @@ pub(crate) struct GStreamer {
     pool: DBPool,
     /// should we repeat once?
     repeat_once: AtomicBool,
+    /// what we play after the current track ends
+    repeat: Mutex<RepeatMode>,
+    /// the order we play the tracks in
+    play_mode: Mutex<PlayMode>,
+    /// the shuffled order and the tab it belongs to, created when we first need it
+    play_order: Mutex<Option<(usize, PlayOrder)>>,
+    /// the position we queued with about-to-finish for gapless playback, it starts playing at the next stream start
+    queued: Mutex<Option<usize>>,
+    /// our volume, the output plays with it while the live stream does not
+    volume: Mutex<f64>,
+    /// crossfade duration in seconds, zero disables crossfading
+    crossfade: AtomicU64,
+    /// changes on every crossfade start and stop, a running volume ramp stops if it changed
+    fade_id: Arc<AtomicUsize>,
+    /// the active equalizer preset, None if the gains were set by hand
+    equalizer_preset: Mutex<Option<String>>,
+    /// the ReplayGain settings, shared with the pad probes
+    replaygain: Arc<Mutex<ReplayGainJson>>,
+    /// the ReplayGain tags of the track each player plays, so we can restore them if ReplayGain gets enabled again
+    replaygain_tags: [Arc<Mutex<Option<gstreamer::TagList>>>; 2],
+    /// the output we play to
+    output: Mutex<AudioOutput>,
+    /// the playback rate, scaletempo keeps the pitch
+    rate: Mutex<f64>,
+    /// the sleep timer and when it ends if it runs for some minutes
+    sleep_timer: Mutex<Option<(SleepTimer, Option<Instant>)>>,
+    /// do we skip to the next track if a track cannot be played
+    skip_errors: AtomicBool,
+    /// do we pass over tracks whose file is missing when we go to the next track
+    skip_missing: AtomicBool,
+    /// how many tracks failed in a row
+    failures: AtomicUsize,
+    /// playback errors the gui did not fetch yet
+    errors: Mutex<Vec<PlaybackErrorJson>>,
+    /// where we were before the restart, the first play of that track starts there
+    resume: Mutex<Option<ResumePosition>>,
+    /// do we load the track of `resume` paused on startup
+    resume_paused: AtomicBool,
+    /// the tracks we remember the position in
+    bookmark_rules: BookmarkRules,
+    /// what the playing stream announces in its tags, i.e., the ICY title of a radio station
+    stream_title: Mutex<Option<String>>,
+    /// milliseconds between the visualization data, zero while nobody listens, shared with the pad probes
+    visualization_interval: Arc<AtomicU64>,
+    /// the latest levels and spectrum of the active player
+    visualization: tokio::sync::watch::Sender<VisualizationJson>,
+    /// serves what we play over http if it is turned on in the preferences
+    live_stream: Option<LiveStream>,
 }
 
 impl Drop for GStreamer {
     fn drop(&mut self) {
-        self.element
-            .set_state(gstreamer::State::Null)
-            .expect("Error in setting gstreamer state: Null");
+        for player in &self.players {
+            set_state(player, gstreamer::State::Null);
+        }
+        set_state(self.output_pipeline.upcast_ref(), gstreamer::State::Null);
+    }
+}
+
+/// creates a playbin with our audio sink bin that hands the audio in `MIXER_CAPS` to its appsink `playersink`
+fn make_player() -> Result<gstreamer::Element, String> {
+    let playbin = gstreamer::ElementFactory::make("playbin")
+        .build()
+        .map_err(|e| format!("Cannot do gstreamer: {}", e))?;
+    /* based on
+           bin = gst_bin_new ("audio_sink_bin");
+    gst_bin_add_many (GST_BIN (bin), equalizer, convert, sink, NULL);
+    gst_element_link_many (equalizer, convert, sink, NULL);
+
+    pad = gst_element_get_static_pad (equalizer, "sink");
+    ghost_pad = gst_ghost_pad_new ("sink", pad);
+    gst_pad_set_active (ghost_pad, TRUE);
+    gst_element_add_pad (bin, ghost_pad);
+    gst_object_unref (pad);
+
+    /* Configure the equalizer */
+    g_object_set (G_OBJECT (equalizer), "band1", (gdouble)-24.0, NULL);
+    g_object_set (G_OBJECT (equalizer), "band2", (gdouble)-24.0, NULL);
+
+    /* Set playbin's audio sink to be our sink bin */
+    g_object_set (GST_OBJECT (pipeline), "audio-sink", bin, NULL);
+    */
+    let audioconvert1 = gstreamer::ElementFactory::make("audioconvert")
+        .build()
+        .expect("Error in convert");
+    let scaletempo = gstreamer::ElementFactory::make("scaletempo")
+        .build()
+        .expect("Error in scaletempo");
+    let equalizer = gstreamer::ElementFactory::make("equalizer-10bands")
+        .name("equalizer")
+        .build()
+        .expect("Error in equalizer");
+    let rgvolume = gstreamer::ElementFactory::make("rgvolume")
+        .name("rgvolume")
+        .build()
+        .expect("Error in rgvolume");
+    let rglimit = gstreamer::ElementFactory::make("rglimiter")
+        .name("rglimiter")
+        .build()
+        .expect("Errror in rglimit");
+    let audioconvert2 = gstreamer::ElementFactory::make("audioconvert")
+        .build()
+        .expect("Error in convert2");
+    let audioresample = gstreamer::ElementFactory::make("audioresample")
+        .name("audioresample")
+        .build()
+        .expect("Errror in resample");
+    let capsfilter = gstreamer::ElementFactory::make("capsfilter")
+        .property("caps", mixer_caps())
+        .build()
+        .expect("Error in capsfilter");
+    let tee = gstreamer::ElementFactory::make("tee")
+        .build()
+        .expect("Error in tee");
+    let playerqueue = gstreamer::ElementFactory::make("queue")
+        .name("playerqueue")
+        .build()
+        .expect("Error in playerqueue");
+    let playersink = gstreamer::ElementFactory::make("appsink")
+        .name("playersink")
+        .property("sync", true)
+        .property("emit-signals", true)
+        .build()
+        .expect("Error in playersink");
+    // the visualization branch only analyses the audio, it never holds up the sink
+    let visqueue = gstreamer::ElementFactory::make("queue")
+        .name("visqueue")
+        .property_from_str("leaky", "downstream")
+        .build()
+        .expect("Error in visqueue");
+    let level = gstreamer::ElementFactory::make("level")
+        .name("level")
+        .build()
+        .expect("Error in level");
+    let spectrum = gstreamer::ElementFactory::make("spectrum")
+        .name("spectrum")
+        .property("bands", SPECTRUM_BANDS)
+        .property("threshold", SPECTRUM_THRESHOLD)
+        .build()
+        .expect("Error in spectrum");
+    let vissink = gstreamer::ElementFactory::make("fakesink")
+        .property("sync", true)
+        .property("async", false)
+        .build()
+        .expect("Error in vissink");
+    let bin = gstreamer::Bin::new();
+    bin.add_many([
+        &audioconvert1,
+        &scaletempo,
+        &equalizer,
+        &rgvolume,
+        &rglimit,
+        &audioconvert2,
+        &audioresample,
+        &capsfilter,
+        &tee,
+        &playerqueue,
+        &playersink,
+        &visqueue,
+        &level,
+        &spectrum,
+        &vissink,
+    ])
+    .expect("Could not add");
+    gstreamer::Element::link_many([
+        &audioconvert1,
+        &scaletempo,
+        &equalizer,
+        &rgvolume,
+        &rglimit,
+        &audioconvert2,
+        &audioresample,
+        &capsfilter,
+        &tee,
+        &playerqueue,
+        &playersink,
+    ])
+    .expect("Could not link");
+    gstreamer::Element::link_many([&tee, &visqueue, &level, &spectrum, &vissink])
+        .expect("Could not link visualization");
+    let pad = audioconvert1.static_pad("sink").expect("Could not get pad");
+    let ghost = gstreamer::GhostPad::with_target(&pad).expect("Could not create ghost");
+    ghost.set_active(true).expect("Could not set active");
+    bin.add_pad(&ghost).expect("Could not add pad");
+    playbin.set_property("audio-sink", bin);
+    Ok(playbin)
+}
+
+/// the caps of `MIXER_CAPS`
+fn mixer_caps() -> gstreamer::Caps {
+    MIXER_CAPS.parse().expect("Invalid mixer caps")
+}
+
+/// Creates the pipeline that mixes the audio of both players, plays it to `sink` and encodes it for `live_stream`.
+/// Our volume only applies to `sink`, so muting does not silence the listeners of the stream.
+/// Returns it with the mixer pads of the players.
+fn make_output(
+    sink: gstreamer::Element,
+    live_stream: Option<&LiveStream>,
+) -> Result<(gstreamer::Pipeline, [gstreamer::Pad; 2]), String> {
+    let pipeline = gstreamer::Pipeline::with_name("output");
+    let mixer = gstreamer::ElementFactory::make("audiomixer")
+        .name("mixer")
+        .build()
+        .map_err(|e| format!("Cannot do gstreamer: {}", e))?;
+    let tee = gstreamer::ElementFactory::make("tee")
+        .build()
+        .expect("Error in tee");
+    let volume = gstreamer::ElementFactory::make("volume")
+        .name("volume")
+        .property("volume", DEFAULT_VOLUME)
+        .build()
+        .expect("Error in volume");
+    let sinkqueue = gstreamer::ElementFactory::make("queue")
+        .name("sinkqueue")
+        .build()
+        .expect("Error in sinkqueue");
+    pipeline
+        .add_many([&mixer, &tee, &volume, &sinkqueue, &sink])
+        .expect("Could not add");
+    gstreamer::Element::link_many([&mixer, &tee, &volume, &sinkqueue, &sink])
+        .expect("Could not link");
+    let mut pads = Vec::new();
+    for index in 0..2 {
+        // the players push what they decoded in time, so the mixer only has to wait for the latency
+        let src = gstreamer::ElementFactory::make("appsrc")
+            .name(format!("playersrc{}", index))
+            .property("caps", mixer_caps())
+            .property("is-live", true)
+            .property("do-timestamp", true)
+            .property_from_str("format", "time")
+            .property("min-latency", MIXER_LATENCY.nseconds() as i64)
+            .build()
+            .expect("Error in playersrc");
+        pipeline.add(&src).expect("Could not add");
+        src.link(&mixer).expect("Could not link player");
+        let pad = src
+            .static_pad("src")
+            .and_then(|pad| pad.peer())
+            .ok_or_else(|| String::from("Could not find mixer pad"))?;
+        pads.push(pad);
+    }
+    if let Some(live_stream) = live_stream {
+        let branch =
+            gstreamer::parse::bin_from_description(&live_stream.branch_description(), true)
+                .map_err(|e| format!("Could not create stream encoder: {}", e))?;
+        pipeline.add(&branch).expect("Could not add stream");
+        tee.link(&branch).expect("Could not link stream");
+    }
+    // the players run on the system clock as well
+    pipeline.use_clock(Some(&gstreamer::SystemClock::obtain()));
+    let pads = pads.try_into().expect("Two mixer pads");
+    Ok((pipeline, pads))
+}
+
+/// returns the element called `name` in the audio sink bin of `player`
+fn sink_element(player: &gstreamer::Element, name: &str) -> Option<gstreamer::Element> {
+    player
+        .property::<Option<gstreamer::Element>>("audio-sink")
+        .and_then(|sink| sink.downcast::<gstreamer::Bin>().ok())
+        .and_then(|bin| bin.by_name(name))
+}
+
+/// does `tags` contain any ReplayGain information
+fn has_replaygain(tags: &gstreamer::TagListRef) -> bool {
+    tags.get::<gstreamer::tags::TrackGain>().is_some()
+        || tags.get::<gstreamer::tags::AlbumGain>().is_some()
+}
+
+/// replaces the sink of `output`, the output has to be in the ready state
+fn replace_sink(output: &gstreamer::Pipeline, sink: gstreamer::Element) -> Result<(), String> {
+    let bin = output.upcast_ref::<gstreamer::Bin>();
+    let sinkqueue = bin
+        .by_name("sinkqueue")
+        .ok_or_else(|| String::from("Could not find sinkqueue"))?;
+    if let Some(old) = bin.by_name(output::SINK_NAME) {
+        old.set_state(gstreamer::State::Null)
+            .map_err(|e| format!("Could not stop old sink: {}", e))?;
+        bin.remove(&old)
+            .map_err(|e| format!("Could not remove old sink: {}", e))?;
+    }
+    bin.add(&sink)
+        .map_err(|e| format!("Could not add sink: {}", e))?;
+    sinkqueue
+        .link(&sink)
+        .map_err(|e| format!("Could not link sink: {}", e))?;
+    sink.sync_state_with_parent()
+        .map_err(|e| format!("Could not set sink state: {}", e))
+}
+
+/// tags that make rgvolume apply no gain
+fn zero_replaygain_tags() -> gstreamer::TagList {
+    let mut tags = gstreamer::TagList::new();
+    {
+        let tags = tags.get_mut().unwrap();
+        tags.add::<gstreamer::tags::TrackGain>(&0.0, gstreamer::TagMergeMode::Replace);
+        tags.add::<gstreamer::tags::AlbumGain>(&0.0, gstreamer::TagMergeMode::Replace);
     }
+    tags
+}
+
+/// Remembers the ReplayGain tags `player` sees and drops them while ReplayGain is off.
+/// The probe sits in front of rgvolume, so tags we send to rgvolume ourselves do not pass it.
+fn add_replaygain_probe(
+    player: &gstreamer::Element,
+    replaygain: Arc<Mutex<ReplayGainJson>>,
+    tags: Arc<Mutex<Option<gstreamer::TagList>>>,
+) {
+    let Some(pad) = sink_element(player, "equalizer").and_then(|eq| eq.static_pad("src")) else {
+        warn!("Could not find equalizer pad, ReplayGain cannot be turned off");
+        return;
+    };
+    pad.add_probe(gstreamer::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+        let Some(gstreamer::PadProbeData::Event(ref event)) = info.data else {
+            return gstreamer::PadProbeReturn::Ok;
+        };
+        match event.view() {
+            gstreamer::EventView::StreamStart(_) => {
+                tags.lock().take();
+            }
+            gstreamer::EventView::Tag(tag) if has_replaygain(tag.tag()) => {
+                *tags.lock() = Some(tag.tag().to_owned());
+                if replaygain.lock().mode == ReplayGainMode::Off {
+                    return gstreamer::PadProbeReturn::Drop;
+                }
+            }
+            _ => {}
+        }
+        gstreamer::PadProbeReturn::Ok
+    });
+}
+
+/// Drops the buffers going into `queue` while `wanted` is false, so the branch behind it does no work
+fn add_branch_probe<F>(queue: Option<gstreamer::Element>, wanted: F)
+where
+    F: Fn() -> bool + Send + Sync + 'static,
+{
+    let Some(pad) = queue.and_then(|q| q.static_pad("sink")) else {
+        warn!("Could not find the queue of a branch");
+        return;
+    };
+    pad.add_probe(gstreamer::PadProbeType::BUFFER, move |_, _| {
+        if wanted() {
+            gstreamer::PadProbeReturn::Ok
+        } else {
+            gstreamer::PadProbeReturn::Drop
+        }
+    });
+}
+
+/// the dB values in the field `name` of a level message, rounded to whole dB
+fn level_values(s: &gstreamer::StructureRef, name: &str) -> Vec<i8> {
+    s.get::<gstreamer::glib::ValueArray>(name)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.get::<f64>().ok())
+                .map(|db| db.clamp(-128.0, 127.0).round() as i8)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// sets the state of `element`, a failure only gets logged as the bus tells us about the reason
+fn set_state(element: &gstreamer::Element, state: gstreamer::State) {
+    if let Err(e) = element.set_state(state) {
+        warn!("Could not set gstreamer state {:?}: {}", state, e);
+    }
+}
+
+/// saves `value` as json into the preference `key`
+fn save_json_preference<T: Serialize>(key: &str, value: &T) {
+    match serde_json::to_string(value) {
+        Ok(json) => {
+            if let Err(e) = crate::utils::save_preference(key, json) {
+                warn!("Could not save {}: {}", key, e);
+            }
+        }
+        Err(e) => warn!("Could not serialize {}: {}", key, e),
+    }
+}
+
+fn resume_file() -> Result<PathBuf, String> {
+    crate::utils::get_config_dir().map(|p| p.join(RESUME_FILE))
+}
+
+/// the resume position we saved, None if there is none or we cannot read it
+fn load_resume_position() -> Option<ResumePosition> {
+    let string = std::fs::read_to_string(resume_file().ok()?).ok()?;
+    serde_json::from_str::<Option<ResumePosition>>(&string).ok()?
+}
+
+/// parses comma separated equalizer gains as we save them in the preferences
+fn parse_gains(s: &str) -> Option<[f64; equalizer::BANDS]> {
+    let gains = s
+        .split(',')
+        .map(|g| g.trim().parse::<f64>().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    gains.try_into().ok()
 }
 
 /// create new gstreamer object and return it
@@ -38,106 +505,321 @@ pub(crate) fn new(
     current_playlist: PlaylistTabsPtr,
     pool: DBPool,
     msg_bus: tokio::sync::broadcast::Sender<GStreamerMessage>,
+) -> Result<Arc<GStreamer>, String> {
+    with_preferences(
+        current_playlist,
+        pool,
+        msg_bus,
+        &crate::utils::load_preferences(),
+        load_resume_position(),
+        BookmarkRules::load(),
+    )
+}
+
+/// creates the gstreamer object with the settings in `prefs` and starts its threads
+fn with_preferences(
+    current_playlist: PlaylistTabsPtr,
+    pool: DBPool,
+    msg_bus: tokio::sync::broadcast::Sender<GStreamerMessage>,
+    prefs: &PreferencesMap<String>,
+    resume: Option<ResumePosition>,
+    bookmark_rules: BookmarkRules,
 ) -> Result<Arc<GStreamer>, String> {
     gstreamer::init().unwrap();
-    let element = {
-        let playbin = gstreamer::ElementFactory::make("playbin")
-            .build()
-            .map_err(|e| format!("Cannot do gstreamer: {}", e))?;
-        playbin.set_property("volume", 0.5_f64);
-        /* based on
-               bin = gst_bin_new ("audio_sink_bin");
-        gst_bin_add_many (GST_BIN (bin), equalizer, convert, sink, NULL);
-        gst_element_link_many (equalizer, convert, sink, NULL);
-
-        pad = gst_element_get_static_pad (equalizer, "sink");
-        ghost_pad = gst_ghost_pad_new ("sink", pad);
-        gst_pad_set_active (ghost_pad, TRUE);
-        gst_element_add_pad (bin, ghost_pad);
-        gst_object_unref (pad);
-
-        /* Configure the equalizer */
-        g_object_set (G_OBJECT (equalizer), "band1", (gdouble)-24.0, NULL);
-        g_object_set (G_OBJECT (equalizer), "band2", (gdouble)-24.0, NULL);
-
-        /* Set playbin's audio sink to be our sink bin */
-        g_object_set (GST_OBJECT (pipeline), "audio-sink", bin, NULL);
-        */
-        let audioconvert1 = gstreamer::ElementFactory::make("audioconvert")
-            .build()
-            .expect("Error in convert");
-        let rgvolume = gstreamer::ElementFactory::make("rgvolume")
-            .build()
-            .expect("Error in rgvolume");
-        let rglimit = gstreamer::ElementFactory::make("rglimiter")
-            .build()
-            .expect("Errror in rglimit");
-        let audioconvert2 = gstreamer::ElementFactory::make("audioconvert")
-            .build()
-            .expect("Error in convert2");
-        let audioresample = gstreamer::ElementFactory::make("audioresample")
-            .build()
-            .expect("Errror in resample");
-        let sink = gstreamer::ElementFactory::make("autoaudiosink")
-            .build()
-            .expect("Errror in sink");
-        let bin = gstreamer::Bin::new();
-        bin.add_many([
-            &audioconvert1,
-            &rgvolume,
-            &rglimit,
-            &audioconvert2,
-            &audioresample,
-            &sink,
-        ])
-        .expect("Could not add");
-        gstreamer::Element::link_many([
-            &audioconvert1,
-            &rgvolume,
-            &rglimit,
-            &audioconvert2,
-            &audioresample,
-            &sink,
-        ])
-        .expect("Could not link");
-        let pad = audioconvert1.static_pad("sink").expect("Could not get pad");
-        let ghost = gstreamer::GhostPad::with_target(&pad).expect("Could not create ghost");
-        ghost.set_active(true).expect("Could not set active");
-        bin.add_pad(&ghost).expect("Could not add pad");
-        playbin.set_property("audio-sink", bin);
-        playbin
+    let output = prefs
+        .get("output")
+        .and_then(|o| serde_json::from_str::<AudioOutput>(o).ok())
+        .unwrap_or_default();
+    let make_sink = || {
+        output::make_sink(&output).or_else(|e| {
+            warn!(
+                "Could not use output {:?}, using the default: {}",
+                output, e
+            );
+            output::make_sink(&AudioOutput::Auto)
+        })
     };
-    let bus = element.bus().unwrap();
+    let live_stream = prefs
+        .get("stream")
+        .and_then(|f| serde_json::from_str::<MixFormat>(f).ok())
+        .and_then(|format| {
+            LiveStream::new(format)
+                .map_err(|e| warn!("Could not start the live stream: {}", e))
+                .ok()
+        });
+    let players = [make_player()?, make_player()?];
+    let (output_pipeline, mixer_pads) = make_output(make_sink()?, live_stream.as_ref())?;
+    let crossfade = prefs
+        .get("crossfade")
+        .and_then(|c| c.parse::<u64>().ok())
+        .unwrap_or(0);
+    let volume = prefs
+        .get("volume")
+        .and_then(|v| v.parse::<u32>().ok())
+        .map_or(DEFAULT_VOLUME, |v| f64::from(v.min(100)) / 100.0);
+    let muted = prefs
+        .get("mute")
+        .and_then(|m| m.parse::<bool>().ok())
+        .unwrap_or(false);
+    let repeat = prefs
+        .get("repeat")
+        .and_then(|r| serde_json::from_str::<RepeatMode>(r).ok())
+        .unwrap_or_default();
+    let play_mode = prefs
+        .get("playmode")
+        .and_then(|m| serde_json::from_str::<PlayMode>(m).ok())
+        .unwrap_or_default();
+    let skip_errors = prefs
+        .get("skiperrors")
+        .and_then(|s| s.parse::<bool>().ok())
+        .unwrap_or(true);
+    let skip_missing = prefs
+        .get("skipmissing")
+        .and_then(|s| s.parse::<bool>().ok())
+        .unwrap_or(true);
+    let resume_paused = prefs
+        .get("resumepaused")
+        .and_then(|r| r.parse::<bool>().ok())
+        .unwrap_or(false);
+    // the order we played in before a restart, so a shuffle continues where it left off
+    let play_order = prefs
+        .get("playorder")
+        .filter(|_| play_mode != PlayMode::Ordered)
+        .and_then(|o| serde_json::from_str::<(usize, PlayOrder)>(o).ok());
+    let rate = prefs
+        .get("rate")
+        .and_then(|r| r.parse::<f64>().ok())
+        .map_or(1.0, |r| r.clamp(MIN_RATE, MAX_RATE));
+    let replaygain = prefs
+        .get("replaygain")
+        .and_then(|r| serde_json::from_str::<ReplayGainJson>(r).ok())
+        .unwrap_or_default();
+    let equalizer_preset = prefs
+        .get("equalizer")
+        .filter(|name| !name.is_empty())
+        .cloned();
+    let equalizer_gains = equalizer_preset
+        .as_deref()
+        .and_then(equalizer::find)
+        .map(|p| p.gains)
+        .or_else(|| {
+            prefs
+                .get("equalizer_gains")
+                .map(String::as_str)
+                .and_then(parse_gains)
+        });
     let res = Arc::new(GStreamer {
-        element,
+        players,
+        output_pipeline,
+        mixer_pads,
+        drain: Mutex::new(None),
+        active: AtomicUsize::new(0),
         current_playlist,
         sender: msg_bus,
         pool,
         repeat_once: AtomicBool::new(false),
+        repeat: Mutex::new(repeat),
+        play_mode: Mutex::new(play_mode),
+        play_order: Mutex::new(play_order),
+        queued: Mutex::new(None),
+        volume: Mutex::new(volume),
+        crossfade: AtomicU64::new(crossfade),
+        fade_id: Arc::new(AtomicUsize::new(0)),
+        equalizer_preset: Mutex::new(equalizer_preset),
+        replaygain: Arc::new(Mutex::new(replaygain)),
+        replaygain_tags: Default::default(),
+        output: Mutex::new(output),
+        rate: Mutex::new(rate),
+        sleep_timer: Mutex::new(None),
+        skip_errors: AtomicBool::new(skip_errors),
+        skip_missing: AtomicBool::new(skip_missing),
+        failures: AtomicUsize::new(0),
+        errors: Mutex::new(Vec::new()),
+        resume: Mutex::new(resume),
+        resume_paused: AtomicBool::new(resume_paused),
+        bookmark_rules,
+        stream_title: Mutex::new(None),
+        visualization_interval: Arc::new(AtomicU64::new(0)),
+        visualization: tokio::sync::watch::Sender::new(VisualizationJson::default()),
+        live_stream,
     });
+    for (player, tags) in res.players.iter().zip(&res.replaygain_tags) {
+        add_replaygain_probe(player, res.replaygain.clone(), tags.clone());
+        let interval = res.visualization_interval.clone();
+        add_branch_probe(sink_element(player, "visqueue"), move || {
+            interval.load(Ordering::SeqCst) > 0
+        });
+    }
 
-    let resc = res.clone();
-    // this has to be a real thread as otherwise the send in gstreamer_handle_eos does not work correctly.
-    tokio::spawn(async move {
-        use gstreamer::MessageView;
-        for msg in bus.iter_timed(gstreamer::ClockTime::NONE) {
-            match msg.view() {
-                MessageView::Eos(..) => {
-                    info!("We found an eos on the bus!");
-                    resc.gstreamer_handle_eos();
-                    info!("returned from eos handling");
+    // the players hand what they decoded to their source in the output
+    for (index, player) in res.players.iter().enumerate() {
+        let (Some(playersink), Some(playersrc)) = (
+            sink_element(player, "playersink"),
+            res.output_pipeline.by_name(&format!("playersrc{}", index)),
+        ) else {
+            return Err(String::from("Could not connect the player to the output"));
+        };
+        playersink.connect("new-sample", false, move |values| {
+            let appsink = values[0]
+                .get::<gstreamer::Element>()
+                .expect("new-sample without appsink");
+            if let Some(sample) =
+                appsink.emit_by_name::<Option<gstreamer::Sample>>("pull-sample", &[])
+            {
+                // the output drops it while it does not play
+                let _ = playersrc.emit_by_name::<gstreamer::FlowReturn>("push-sample", &[&sample]);
+            }
+            Some(gstreamer::FlowReturn::Ok.to_value())
+        });
+    }
+    res.apply_volume();
+    res.output_volume().set_property("mute", muted);
+    res.apply_replaygain();
+    if let Some(gains) = equalizer_gains {
+        res.apply_equalizer_gains(gains);
+    }
+
+    // the encoder of the live stream hands us its buffers
+    if let Some(streamsink) = res.output_pipeline.by_name("streamsink") {
+        let weak: Weak<GStreamer> = Arc::downgrade(&res);
+        add_branch_probe(res.output_pipeline.by_name("streamqueue"), move || {
+            weak.upgrade().is_some_and(|gst| {
+                gst.live_stream
+                    .as_ref()
+                    .is_some_and(LiveStream::has_listeners)
+            })
+        });
+        let weak: Weak<GStreamer> = Arc::downgrade(&res);
+        streamsink.connect("new-sample", false, move |values| {
+            let appsink = values[0]
+                .get::<gstreamer::Element>()
+                .expect("new-sample without appsink");
+            let sample = appsink.emit_by_name::<Option<gstreamer::Sample>>("pull-sample", &[]);
+            if let (Some(gst), Some(buffer)) =
+                (weak.upgrade(), sample.as_ref().and_then(|s| s.buffer()))
+            {
+                if let (Some(live_stream), Ok(map)) =
+                    (gst.live_stream.as_ref(), buffer.map_readable())
+                {
+                    live_stream.push(
+                        map.to_vec(),
+                        buffer.flags().contains(gstreamer::BufferFlags::HEADER),
+                    );
                 }
-                MessageView::Error(err) => println!("Error {:?}", err),
-                MessageView::StateChanged(state_changed) => {
-                    warn!("Message bus has state change: {:?}", state_changed)
+            }
+            Some(gstreamer::FlowReturn::Ok.to_value())
+        });
+    }
+
+    // this is called from the streaming thread shortly before the track ends, so we can queue the next uri without a gap.
+    // We only keep a weak reference as the element would otherwise keep us alive forever.
+    for player in &res.players {
+        let weak: Weak<GStreamer> = Arc::downgrade(&res);
+        player.connect("about-to-finish", false, move |values| {
+            let playbin = values[0]
+                .get::<gstreamer::Element>()
+                .expect("about-to-finish without playbin");
+            if let Some(uri) = weak
+                .upgrade()
+                .and_then(|gst| gst.gstreamer_about_to_finish(&playbin))
+            {
+                playbin.set_property("uri", uri);
+            }
+            None
+        });
+    }
+
+    for (index, player) in res.players.iter().enumerate() {
+        let bus = player.bus().unwrap();
+        let resc = res.clone();
+        // this has to be a real thread as otherwise the send in gstreamer_handle_eos does not work correctly.
+        tokio::task::spawn_blocking(move || {
+            use gstreamer::MessageView;
+            for msg in bus.iter_timed(gstreamer::ClockTime::NONE) {
+                if resc.active.load(Ordering::SeqCst) != index {
+                    // this player is fading out and gets stopped by the volume ramp
+                    continue;
                 }
-                MessageView::Tag(_) => {
-                    warn!("Found tag msg")
+                match msg.view() {
+                    MessageView::Eos(..) => {
+                        info!("We found an eos on the bus!");
+                        resc.gstreamer_handle_eos();
+                        info!("returned from eos handling");
+                    }
+                    MessageView::StreamStart(..) => {
+                        info!("We found a stream start on the bus!");
+                        resc.gstreamer_handle_stream_start();
+                    }
+                    MessageView::Error(err) => {
+                        let error = match err.debug() {
+                            Some(debug) => format!("{} ({})", err.error(), debug),
+                            None => err.error().to_string(),
+                        };
+                        resc.playback_failed(resc.playing_path(), error);
+                    }
+                    MessageView::StateChanged(state_changed) => {
+                        warn!("Message bus has state change: {:?}", state_changed)
+                    }
+                    MessageView::Tag(tag) => {
+                        resc.gstreamer_handle_tags(&tag.tags());
+                    }
+                    MessageView::Element(element) => {
+                        if let (Some(src), Some(s)) = (element.src(), element.structure()) {
+                            resc.gstreamer_handle_analysis(src, s);
+                        }
+                    }
+                    m => warn!("Found message {:?}", m),
                 }
-                m => warn!("Found message {:?}", m),
             }
-        }
-    });
+        });
+    }
+    {
+        let bus = res.output_pipeline.bus().unwrap();
+        let resc = res.clone();
+        tokio::task::spawn_blocking(move || {
+            use gstreamer::MessageView;
+            for msg in bus.iter_timed(gstreamer::ClockTime::NONE) {
+                match msg.view() {
+                    MessageView::Eos(..) => resc.finish_drain(),
+                    MessageView::Error(err) => {
+                        let error = match err.debug() {
+                            Some(debug) => format!("{} ({})", err.error(), debug),
+                            None => err.error().to_string(),
+                        };
+                        resc.output_failed(error);
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    {
+        let resc = res.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(PLAYBACK_CHECK).await;
+                resc.check_crossfade();
+                // a new track starts with the normal rate
+                resc.check_rate();
+                resc.check_drain();
+            }
+        });
+    }
+    {
+        let resc = res.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(RESUME_SAVE).await;
+                if resc.get_state() == GStreamerMessage::Playing {
+                    resc.save_resume_position();
+                }
+            }
+        });
+    }
+    if resume_paused {
+        res.resume_paused();
+    }
 
     //let resc = res.clone();
     //glin::timeout_add(250, move || resc.gstreamer_update_gui());
@@ -152,9 +834,15 @@ impl GStreamer {
         //everytime we call return, we do not want to send the message we got to the gui, as it will be done in a subcall we have done
         match action {
             GStreamerAction::Next => {
+                self.save_bookmark();
                 self.repeat_once.store(false, Ordering::SeqCst);
-                if let Some(i) = self.current_playlist.next_or_eol() {
-                    self.do_gstreamer_action(GStreamerAction::Play(i));
+                let crossfade = self.crossfade.load(Ordering::SeqCst);
+                if let Some(i) = self.advance_to(self.peek_following(false)) {
+                    if crossfade > 0 && self.get_state() == GStreamerMessage::Playing {
+                        self.crossfade_to(i, Duration::from_secs(crossfade));
+                    } else {
+                        self.do_gstreamer_action(GStreamerAction::Play(i));
+                    }
                 } else {
                     self.do_gstreamer_action(GStreamerAction::Stop);
                 }
@@ -162,9 +850,7 @@ impl GStreamer {
             }
             GStreamerAction::Playing => {
                 if self.get_state() == GStreamerMessage::Pausing {
-                    self.element
-                        .set_state(gstreamer::State::Playing)
-                        .expect("Error in setting gstreamer state");
+                    set_state(self.element(), gstreamer::State::Playing);
                 } else {
                     self.do_gstreamer_action(GStreamerAction::Play(
                         self.current_playlist.current_position(),
@@ -175,9 +861,8 @@ impl GStreamer {
             GStreamerAction::Pausing => {
                 //let is_playing = GStreamerMessage::Playing == self.get_state();
                 //if is_playing {
-                self.element
-                    .set_state(gstreamer::State::Paused)
-                    .expect("Error setting gstreamer state");
+                self.stop_fade();
+                set_state(self.element(), gstreamer::State::Paused);
                 //} else {
                 //    self.do_gstreamer_action(GStreamerAction::Play(
                 //        self.current_playlist.current_position(),
@@ -186,8 +871,16 @@ impl GStreamer {
                 //}
             }
             GStreamerAction::Previous => {
+                self.save_bookmark();
                 self.repeat_once.store(false, Ordering::SeqCst);
-                if let Some(i) = self.current_playlist.previous() {
+                let mode = *self.play_mode.lock();
+                let previous = match mode {
+                    PlayMode::Ordered => self.current_playlist.previous(),
+                    PlayMode::Shuffle | PlayMode::AlbumShuffle => {
+                        self.in_play_order(|order, current| order.previous(current))
+                    }
+                };
+                if let Some(i) = previous {
                     self.do_gstreamer_action(GStreamerAction::Play(i));
                 } else {
                     self.do_gstreamer_action(GStreamerAction::Stop);
@@ -195,25 +888,33 @@ impl GStreamer {
                 return;
             }
             GStreamerAction::Stop => {
-                self.element
-                    .set_state(gstreamer::State::Ready)
-                    .expect("Error setting gstreamer state");
+                self.failures.store(0, Ordering::SeqCst);
+                self.stop_players();
             }
             GStreamerAction::Play(i) => {
+                self.save_bookmark();
+                self.stop_fade();
+                self.queued.lock().take();
                 self.current_playlist.set(i);
                 if let Some(uri) = self.current_playlist.get_current_uri() {
-                    if !self
-                        .current_playlist
-                        .get_current_path()
-                        .expect("URI Error")
-                        .exists()
-                    {
-                        // if the file does not exist inform the gui and stop playing instead of crashing
-                        self.sender
-                            .send(GStreamerMessage::FileNotFound)
-                            .expect("Error in sending");
-                        return self.do_gstreamer_action(GStreamerAction::Stop);
+                    // the auto ReplayGain mode depends on the track
+                    self.apply_replaygain();
+                    let path = self.current_playlist.get_current_path();
+                    if !self.current_playlist.is_available_at(i) {
+                        // if the file does not exist inform the gui and skip it instead of crashing
+                        if let Err(e) = self.sender.send(GStreamerMessage::FileNotFound) {
+                            warn!("Could not broadcast, ignoring: {}", e);
+                        }
+                        return self.playback_failed(path, String::from("File not found"));
                     }
+                    // the first play of the track we played before the restart continues there
+                    let resume = self
+                        .resume
+                        .lock()
+                        .take()
+                        .filter(|r| Some(&r.path) == path.as_ref())
+                        .map(|r| r.elapsed)
+                        .or_else(|| self.bookmark(i));
                     info!(
                         "Playing uri: {:?}",
                         self.current_playlist.get_current_path()
@@ -222,22 +923,20 @@ impl GStreamer {
                     //looking at gstreamer state transition diagram
                     //https://gstreamer.freedesktop.org/documentation/additional/design/states.html?gi-language=c
                     if self.get_state() == GStreamerMessage::Playing {
-                        self.element
-                            .set_state(gstreamer::State::Paused)
-                            .expect("Error setting gstreamer state");
+                        set_state(self.element(), gstreamer::State::Paused);
                     }
-                    self.element
-                        .set_state(gstreamer::State::Ready)
-                        .expect("Errorr in setting gstreamer state");
-
-                    self.element.set_property("uri", uri);
-                    self.element
-                        .set_state(gstreamer::State::Playing)
-                        .expect("Error setting gstreamer state");
+                    set_state(self.element(), gstreamer::State::Ready);
+
+                    self.element().set_property("uri", uri);
+                    if let Some(resume) = resume {
+                        info!("Resuming at {}s", resume);
+                        self.preroll_at(resume);
+                    }
+                    set_state(self.element(), gstreamer::State::Playing);
                     info!("gstreamer state: {:?}", self.get_state());
                     info!(
                         "gstreamer real state: {:?}",
-                        self.element.state(gstreamer::ClockTime::SECOND)
+                        self.element().state(gstreamer::ClockTime::SECOND)
                     );
                 } else {
                     info!("Stopping gstreamer because we did not find next track");
@@ -247,29 +946,1164 @@ impl GStreamer {
                 }
             }
             GStreamerAction::Seek(pos) => {
-                let time = gstreamer::ClockTime::from_seconds(pos);
-                self.element
-                    .seek_simple(gstreamer::SeekFlags::FLUSH, time)
-                    .expect("Error in seeking");
+                return self.seek_to(pos);
+            }
+            GStreamerAction::SeekBy(offset) => {
+                let target = self
+                    .get_position()
+                    .unwrap_or(0)
+                    .saturating_add_signed(offset);
+                let duration = self
+                    .element()
+                    .query_duration::<gstreamer::ClockTime>()
+                    .map(gstreamer::ClockTime::useconds);
+                // like MPRIS wants it, seeking past the end goes to the next track
+                if duration.is_some_and(|d| target > d) {
+                    return self.do_gstreamer_action(GStreamerAction::Next);
+                }
+                return self.seek_to(target);
             }
             GStreamerAction::RepeatOnce => {
                 self.repeat_once.store(true, Ordering::SeqCst);
             }
+            GStreamerAction::SetRepeat(mode) => {
+                *self.repeat.lock() = mode;
+                save_json_preference("repeat", &mode);
+            }
+            GStreamerAction::SetPlayMode(mode) => {
+                *self.play_mode.lock() = mode;
+                // a new shuffle starts at the current track, ordered play just continues from it
+                self.play_order.lock().take();
+                save_json_preference("playmode", &mode);
+            }
+            GStreamerAction::SetVolume(percent) => {
+                let percent = percent.min(100);
+                let volume = f64::from(percent) / 100.0;
+                *self.volume.lock() = volume;
+                self.apply_volume();
+                if let Err(e) = crate::utils::save_preference("volume", percent.to_string()) {
+                    warn!("Could not save volume: {}", e);
+                }
+                return self.send_volume();
+            }
+            GStreamerAction::Mute(muted) => {
+                self.output_volume().set_property("mute", muted);
+                if let Err(e) = crate::utils::save_preference("mute", muted.to_string()) {
+                    warn!("Could not save mute: {}", e);
+                }
+                return self.send_volume();
+            }
         }
+        self.sync_output();
         if let Err(e) = self.sender.send(action.into()) {
             warn!("Could not broadcast, ignoring: {}", e);
         }
     }
 
+    /// sets the output to the state of the active player, so the sink is only open while we play
+    fn sync_output(&self) {
+        let state = match self.get_state() {
+            GStreamerMessage::Playing => gstreamer::State::Playing,
+            GStreamerMessage::Pausing => gstreamer::State::Paused,
+            _ => gstreamer::State::Ready,
+        };
+        let mut drain = self.drain.lock();
+        if let Some(running) = drain.as_ref() {
+            if running.sink.is_some() || state == gstreamer::State::Ready {
+                // the output stops or switches the sink once it played out
+                return;
+            }
+            // we play again before the output played out, going to ready leaves the EOS behind
+            drain.take();
+            set_state(self.output_pipeline.upcast_ref(), gstreamer::State::Ready);
+        }
+        let current = self.output_pipeline.current_state();
+        if state == gstreamer::State::Ready && current == gstreamer::State::Playing {
+            *drain = Some(self.drain_output(None));
+        } else if current != state {
+            set_state(self.output_pipeline.upcast_ref(), state);
+        }
+    }
+
+    /// Sends an EOS through the playing output, so an encoder writes out everything it still holds.
+    /// We do not wait for it, `finish_drain` stops the output once the sink got it.
+    fn drain_output(&self, sink: Option<gstreamer::Element>) -> Drain {
+        if !self
+            .output_pipeline
+            .send_event(gstreamer::event::Eos::new())
+        {
+            warn!("Could not send EOS to the output");
+        }
+        Drain {
+            deadline: Instant::now() + OUTPUT_DRAIN,
+            sink,
+        }
+    }
+
+    /// stops the output after it played out and switches to the sink we wait for
+    fn finish_drain(&self) {
+        {
+            let mut drain = self.drain.lock();
+            let Some(finished) = drain.take() else {
+                return;
+            };
+            set_state(self.output_pipeline.upcast_ref(), gstreamer::State::Ready);
+            if let Some(sink) = finished.sink {
+                if let Err(e) = replace_sink(&self.output_pipeline, sink) {
+                    warn!("{}", e);
+                }
+            }
+        }
+        self.sync_output();
+    }
+
+    /// stops the output if the EOS did not reach the sink in time, needs to be called regularly
+    fn check_drain(&self) {
+        let expired = self
+            .drain
+            .lock()
+            .as_ref()
+            .is_some_and(|d| d.deadline <= Instant::now());
+        if expired {
+            warn!("The output did not play out in {:?}", OUTPUT_DRAIN);
+            self.finish_drain();
+        }
+    }
+
+    /// Handles an error of the output, i.e., the device went away or the file is not writable.
+    /// The track is fine, so we stop without skipping it and keep the failure count.
+    fn output_failed(&self, error: String) {
+        let error = format!("Output {:?} failed: {}", self.get_output(), error);
+        warn!("{}", error);
+        self.errors
+            .lock()
+            .push(PlaybackErrorJson { path: None, error });
+        if let Err(e) = self.sender.send(GStreamerMessage::PlaybackError) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+        self.drain.lock().take();
+        set_state(self.output_pipeline.upcast_ref(), gstreamer::State::Ready);
+        if self.get_state() != GStreamerMessage::Stopped {
+            self.stop_players();
+            if let Err(e) = self.sender.send(GStreamerMessage::Stopped) {
+                warn!("Could not broadcast, ignoring: {}", e);
+            }
+        }
+    }
+
+    /// stops the players and forgets what we queued, remembering the position if a bookmark rule applies
+    fn stop_players(&self) {
+        self.save_bookmark();
+        self.stop_fade();
+        self.queued.lock().take();
+        set_state(self.element(), gstreamer::State::Ready);
+        self.set_stream_title(None);
+    }
+
     /// Handle if gstreamer sends us EndOfStream
     pub(crate) fn gstreamer_handle_eos(&self) {
-        use crate::db::UpdatePlayCount;
         info!("Handling EOS");
+        self.queued.lock().take();
+        if let Some(i) = self.finish_track() {
+            self.do_gstreamer_action(GStreamerAction::Play(i));
+        } else {
+            self.do_gstreamer_action(GStreamerAction::Stop);
+        }
+    }
+
+    /// Handles a track we could not play.
+    /// Tells the gui and skips to the following track if we should, otherwise or after too many failures in a row we stop.
+    fn playback_failed(&self, path: Option<std::path::PathBuf>, error: String) {
+        warn!("Could not play {:?}: {}", path, error);
+        self.errors.lock().push(PlaybackErrorJson {
+            path: path.map(|p| p.to_string_lossy().into_owned()),
+            error,
+        });
+        if let Err(e) = self.sender.send(GStreamerMessage::PlaybackError) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+
+        let failures = self.failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.skip_errors.load(Ordering::SeqCst) && failures < MAX_CONSECUTIVE_FAILURES {
+            if let Some(i) = self.advance_to(self.peek_following(false)) {
+                info!("Skipping to {} after {} failures in a row", i, failures);
+                // playing keeps the failure count, only a stop or a finished track resets it
+                return self.do_gstreamer_action(GStreamerAction::Play(i));
+            }
+        } else if failures >= MAX_CONSECUTIVE_FAILURES {
+            warn!("Stopping after {} failed tracks in a row", failures);
+        }
+        self.do_gstreamer_action(GStreamerAction::Stop);
+    }
+
+    /// pauses the active player at `elapsed` seconds into its uri, we wait for the preroll as we cannot seek before
+    fn preroll_at(&self, elapsed: u64) {
+        set_state(self.element(), gstreamer::State::Paused);
+        let _ = self.element().state(gstreamer::ClockTime::from_seconds(5));
+        if let Err(e) = self.seek_with_rate(gstreamer::ClockTime::from_seconds(elapsed)) {
+            warn!("Could not seek to the resume position: {}", e);
+        }
+    }
+
+    /// loads the track we played before the restart and pauses at the position we stopped
+    fn resume_paused(&self) {
+        let Some(resume) = self.resume.lock().take() else {
+            return;
+        };
+        self.current_playlist.set_tab(resume.tab);
+        if self.current_playlist.get_path_at(resume.position).as_ref() != Some(&resume.path) {
+            warn!("The playlist changed, not resuming {:?}", resume.path);
+            return;
+        }
+        self.current_playlist.set(resume.position);
+        self.apply_replaygain();
+        let Some(uri) = self.current_playlist.get_current_uri() else {
+            return;
+        };
+        info!(
+            "Resuming paused at {}s of {:?}",
+            resume.elapsed, resume.path
+        );
+        set_state(self.element(), gstreamer::State::Ready);
+        self.element().set_property("uri", uri);
+        self.preroll_at(resume.elapsed);
+        self.sync_output();
+        if let Err(e) = self.sender.send(GStreamerMessage::Pausing) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+    }
+
+    /// saves where we are in the playing track, so we can resume there after a restart
+    pub(crate) fn save_resume_position(&self) {
+        let playing = matches!(
+            self.get_state(),
+            GStreamerMessage::Playing | GStreamerMessage::Pausing
+        );
+        let resume = match (self.current_playlist.playing(), self.get_elapsed()) {
+            // we cannot seek in a stream
+            (Some((position, track)), Some(elapsed)) if playing && !track.is_stream() => {
+                Some(ResumePosition {
+                    tab: self.current_playlist.current_playing_in(),
+                    position,
+                    path: PathBuf::from(track.path),
+                    elapsed,
+                })
+            }
+            _ => None,
+        };
+        let saved = serde_json::to_vec(&resume)
+            .map_err(|e| e.to_string())
+            .and_then(|json| crate::utils::write_file(&resume_file()?, &json));
+        if let Err(e) = saved {
+            warn!("Could not save the resume position: {}", e);
+        }
+    }
+
+    /// Remembers the position in the playing track if a bookmark rule applies to it.
+    /// We call this when we leave a track before it finished.
+    pub(crate) fn save_bookmark(&self) {
+        if !matches!(
+            self.get_state(),
+            GStreamerMessage::Playing | GStreamerMessage::Pausing
+        ) {
+            return;
+        }
+        let Some((_, track)) = self.current_playlist.playing() else {
+            return;
+        };
+        // the position might already point to the next track while the player still plays the old one
+        if self.playing_path() != Some(PathBuf::from(&track.path)) {
+            return;
+        }
+        let tab = self
+            .current_playlist
+            .tab_name(self.current_playlist.current_playing_in())
+            .unwrap_or_default();
+        if !self.bookmark_rules.applies(&track, &tab) {
+            return;
+        }
+        if let Some(elapsed) = self.get_elapsed().filter(|e| *e > 0) {
+            if let Err(e) = bookmarks::save(&self.pool, &track, elapsed) {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    /// returns the remembered position in the track at `position` of the playing tab if a bookmark rule applies to it
+    fn bookmark(&self, position: usize) -> Option<u64> {
+        let track = self.current_playlist.get_track_at(position)?;
+        let tab = self
+            .current_playlist
+            .tab_name(self.current_playlist.current_playing_in())
+            .unwrap_or_default();
+        if self.bookmark_rules.applies(&track, &tab) {
+            bookmarks::get(&self.pool, &track)
+        } else {
+            None
+        }
+    }
+
+    /// do we load the last track paused on startup
+    pub(crate) fn get_resume_paused(&self) -> bool {
+        self.resume_paused.load(Ordering::SeqCst)
+    }
+
+    /// sets if we load the last track paused at its position on startup
+    pub(crate) fn set_resume_paused(&self, resume_paused: bool) {
+        self.resume_paused.store(resume_paused, Ordering::SeqCst);
+        if let Err(e) = crate::utils::save_preference("resumepaused", resume_paused.to_string()) {
+            warn!("Could not save resumepaused: {}", e);
+        }
+    }
+
+    /// returns and forgets the playback errors since the last call
+    pub(crate) fn take_errors(&self) -> Vec<PlaybackErrorJson> {
+        std::mem::take(&mut *self.errors.lock())
+    }
+
+    /// do we skip tracks we cannot play
+    pub(crate) fn get_skip_errors(&self) -> bool {
+        self.skip_errors.load(Ordering::SeqCst)
+    }
+
+    /// sets if we skip tracks we cannot play or stop
+    pub(crate) fn set_skip_errors(&self, skip: bool) {
+        self.skip_errors.store(skip, Ordering::SeqCst);
+        if let Err(e) = crate::utils::save_preference("skiperrors", skip.to_string()) {
+            warn!("Could not save skiperrors: {}", e);
+        }
+    }
+
+    /// do we pass over missing files when we go to the next track
+    pub(crate) fn get_skip_missing(&self) -> bool {
+        self.skip_missing.load(Ordering::SeqCst)
+    }
+
+    /// sets if we pass over missing files or try them, which reports them as playback errors
+    pub(crate) fn set_skip_missing(&self, skip: bool) {
+        self.skip_missing.store(skip, Ordering::SeqCst);
+        if let Err(e) = crate::utils::save_preference("skipmissing", skip.to_string()) {
+            warn!("Could not save skipmissing: {}", e);
+        }
+    }
+
+    /// takes the now playing title from the tags if we play a stream, local files have their tags in the database
+    fn gstreamer_handle_tags(&self, tags: &gstreamer::TagList) {
+        if !self
+            .current_playlist
+            .playing()
+            .is_some_and(|(_, track)| track.is_stream())
+        {
+            return;
+        }
+        if let Some(title) = tags.get::<gstreamer::tags::Title>() {
+            self.set_stream_title(Some(title.get().to_string()));
+        }
+    }
+
+    /// sets the title the playing stream announces and broadcasts it if it changed
+    fn set_stream_title(&self, title: Option<String>) {
+        {
+            let mut stream_title = self.stream_title.lock();
+            if *stream_title == title {
+                return;
+            }
+            info!("Stream title changed to {:?}", title);
+            *stream_title = title;
+        }
+        if let Err(e) = self.sender.send(GStreamerMessage::StreamTitleChanged) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+    }
+
+    /// what we announce to the listeners of the live stream, the title a radio station announces or artist and title of the track
+    pub(crate) fn now_playing_title(&self) -> String {
+        if let Some(title) = self.get_stream_title() {
+            return title;
+        }
+        self.current_playlist
+            .playing()
+            .map(|(_, track)| {
+                if track.artist.is_empty() {
+                    track.title
+                } else {
+                    format!("{} - {}", track.artist, track.title)
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    /// the live stream of what we play, None if it is off
+    pub(crate) fn live_stream(&self) -> Option<&LiveStream> {
+        self.live_stream.as_ref()
+    }
+
+    /// returns what the playing stream announces, None if we do not play a stream or it did not tell us yet
+    pub(crate) fn get_stream_title(&self) -> Option<String> {
+        self.stream_title.lock().clone()
+    }
+
+    /// takes the levels and the spectrum from the messages of level and spectrum in the active player, we tell the listeners on every level message
+    fn gstreamer_handle_analysis(&self, src: &gstreamer::Object, s: &gstreamer::StructureRef) {
+        // the bus can still hold messages of the player that became inactive
+        let from_active = |name| {
+            sink_element(self.element(), name)
+                .is_some_and(|e| e.upcast_ref::<gstreamer::Object>() == src)
+        };
+        if s.name() == "level" && from_active("level") {
+            let rms = level_values(s, "rms");
+            let peak = level_values(s, "peak");
+            self.visualization.send_modify(|v| {
+                v.rms = rms;
+                v.peak = peak;
+            });
+        } else if s.name() == "spectrum" && from_active("spectrum") {
+            if let Ok(magnitudes) = s.get::<gstreamer::List>("magnitude") {
+                let bands = magnitudes
+                    .iter()
+                    .filter_map(|m| m.get::<f32>().ok())
+                    .map(|db| db.clamp(-128.0, 127.0).round() as i8)
+                    .collect();
+                self.visualization.send_if_modified(|v| {
+                    v.bands = bands;
+                    false
+                });
+            }
+        }
+    }
+
+    /// returns the milliseconds between the visualization data, None if it is off
+    pub(crate) fn get_visualization(&self) -> Option<u64> {
+        Some(self.visualization_interval.load(Ordering::SeqCst)).filter(|i| *i > 0)
+    }
+
+    /// turns the visualization data on with `interval` milliseconds between them or off with None.
+    /// Level and spectrum only get audio while it is on.
+    pub(crate) fn set_visualization(&self, interval: Option<u64>) {
+        let interval = interval.map_or(0, |i| i.max(MIN_VISUALIZATION_INTERVAL));
+        if interval > 0 {
+            let nanos = interval * 1_000_000;
+            for element in self
+                .sink_elements("level")
+                .into_iter()
+                .chain(self.sink_elements("spectrum"))
+            {
+                element.set_property("interval", nanos);
+            }
+        }
+        self.visualization_interval
+            .store(interval, Ordering::SeqCst);
+    }
+
+    /// receives the levels and the spectrum of the active player while the visualization is on
+    pub(crate) fn subscribe_visualization(
+        &self,
+    ) -> tokio::sync::watch::Receiver<VisualizationJson> {
+        self.visualization.subscribe()
+    }
+
+    /// the path of the file the active player plays
+    fn playing_path(&self) -> Option<PathBuf> {
+        self.element()
+            .property::<Option<String>>("current-uri")
+            .and_then(|uri| gstreamer::glib::filename_from_uri(&uri).ok())
+            .map(|(path, _)| path)
+    }
+
+    /// the player that plays the current track
+    fn element(&self) -> &gstreamer::Element {
+        &self.players[self.active.load(Ordering::SeqCst)]
+    }
+
+    /// the player that does not play the current track, i.e., the one fading out
+    fn inactive_element(&self) -> &gstreamer::Element {
+        &self.players[1 - self.active.load(Ordering::SeqCst)]
+    }
+
+    /// Returns the position we play after the current track.
+    /// Returns None if we changed tabs, are at the end or the sleep timer stops, so that the track boundary decides.
+    fn upcoming(&self) -> Option<usize> {
+        let next =
+            if self.current_playlist.current_tab() != self.current_playlist.current_playing_in() {
+                None
+            } else {
+                self.peek_following(true)
+            };
+        next.filter(|next| !self.sleeps_before(Some(*next)))
+    }
+
+    /// Returns the position we play after the current track with our repeat mode without changing anything.
+    /// `track_end` is true if the track ended by itself, so we repeat it if we should.
+    fn peek_following(&self, track_end: bool) -> Option<usize> {
+        let repeat = *self.repeat.lock();
+        let next = if track_end
+            && (self.repeat_once.load(Ordering::Acquire) || repeat == RepeatMode::Track)
+        {
+            Some(self.current_playlist.current_position())
+        } else if repeat == RepeatMode::Playlist {
+            self.peek_next_in_order().or_else(|| self.first_in_order())
+        } else {
+            self.peek_next_in_order()
+        };
+        if self.skip_missing.load(Ordering::SeqCst) {
+            self.skip_missing_from(next)
+        } else {
+            next
+        }
+    }
+
+    /// Returns `next` or the first position after it in our play mode whose file exists
+    fn skip_missing_from(&self, mut next: Option<usize>) -> Option<usize> {
+        let wraps = *self.repeat.lock() == RepeatMode::Playlist;
+        // we look at every track at most once, so a tab without any existing file does not loop forever
+        for _ in 0..self.current_playlist.track_count() {
+            let i = next?;
+            if self.current_playlist.is_available_at(i) {
+                return Some(i);
+            }
+            info!(
+                "Skipping missing {:?}",
+                self.current_playlist.get_path_at(i)
+            );
+            next = match self.next_in_order_after(i) {
+                None if wraps => self.first_in_order(),
+                next => next,
+            };
+        }
+        None
+    }
+
+    /// Returns the position after the current one in our play mode
+    fn peek_next_in_order(&self) -> Option<usize> {
+        let mode = *self.play_mode.lock();
+        match mode {
+            PlayMode::Ordered => self.current_playlist.peek_next(),
+            PlayMode::Shuffle | PlayMode::AlbumShuffle => {
+                self.in_play_order(|order, current| order.next(current))
+            }
+        }
+    }
+
+    /// Returns the position after `position` in our play mode
+    fn next_in_order_after(&self, position: usize) -> Option<usize> {
+        let mode = *self.play_mode.lock();
+        match mode {
+            PlayMode::Ordered => {
+                Some(position + 1).filter(|i| *i < self.current_playlist.track_count())
+            }
+            PlayMode::Shuffle | PlayMode::AlbumShuffle => {
+                self.in_play_order(|order, _| order.next(position))
+            }
+        }
+    }
+
+    /// Returns up to `n` positions we play after the current track in our play mode, i.e., to check their files
+    pub(crate) fn upcoming_positions(&self, n: usize) -> Vec<usize> {
+        let mut positions = Vec::new();
+        let mut next = self.peek_next_in_order();
+        while let Some(i) = next.filter(|_| positions.len() < n) {
+            positions.push(i);
+            next = self.next_in_order_after(i);
+        }
+        positions
+    }
+
+    /// Returns the position we start with in our play mode, None if there is nothing to play
+    fn first_in_order(&self) -> Option<usize> {
+        let mode = *self.play_mode.lock();
+        match mode {
+            PlayMode::Ordered => self.current_playlist.get_track_at(0).map(|_| 0),
+            PlayMode::Shuffle | PlayMode::AlbumShuffle => {
+                self.in_play_order(|order, _| order.first())
+            }
+        }
+    }
+
+    /// Calls `f` with the shuffled order of the current tab and the current position.
+    /// Creates and saves a new order starting at the current track if the tab or its length changed.
+    fn in_play_order(&self, f: impl FnOnce(&PlayOrder, usize) -> Option<usize>) -> Option<usize> {
+        let mode = *self.play_mode.lock();
+        let tab = self.current_playlist.current_tab();
+        let len = self.current_playlist.track_count();
+        let current = self.current_playlist.current_position();
+        let mut play_order = self.play_order.lock();
+        if !matches!(&*play_order, Some((t, order)) if *t == tab && order.fits(len)) {
+            let order = if mode == PlayMode::AlbumShuffle {
+                PlayOrder::album_shuffled(&self.current_playlist.tracks(), current)
+            } else {
+                PlayOrder::shuffled(len, current)
+            };
+            *play_order = Some((tab, order));
+            save_json_preference("playorder", &*play_order);
+        }
+        play_order.as_ref().and_then(|(_, order)| f(order, current))
+    }
+
+    /// returns the order we play the tracks in
+    pub(crate) fn get_play_mode(&self) -> PlayMode {
+        *self.play_mode.lock()
+    }
+
+    /// Moves the current position to `next` and returns it, None goes back to the start and returns None
+    fn advance_to(&self, next: Option<usize>) -> Option<usize> {
+        if let Some(i) = next {
+            Some(self.current_playlist.set(i))
+        } else {
+            self.current_playlist.set(0);
+            None
+        }
+    }
+
+    /// returns what we play after the current track ends
+    pub(crate) fn get_repeat(&self) -> RepeatMode {
+        *self.repeat.lock()
+    }
+
+    /// Does the sleep timer stop playback when the current track ends and `next` would play
+    fn sleeps_before(&self, next: Option<usize>) -> bool {
+        match *self.sleep_timer.lock() {
+            Some((SleepTimer::AfterTrack, _)) => true,
+            Some((SleepTimer::AfterAlbum, _)) => {
+                let album = self.current_playlist.get_current_track().album;
+                next.and_then(|i| self.current_playlist.get_track_at(i))
+                    .is_none_or(|t| t.album != album)
+            }
+            Some((SleepTimer::Minutes { .. }, _)) | None => false,
+        }
+    }
+
+    /// starts the sleep timer, it runs for at most `MAX_SLEEP_MINUTES`
+    pub(crate) fn set_sleep_timer(&self, timer: SleepTimer) -> Result<(), String> {
+        let deadline = match timer {
+            SleepTimer::Minutes { minutes, .. } if minutes > MAX_SLEEP_MINUTES => {
+                return Err(format!(
+                    "The sleep timer can run for at most {} minutes",
+                    MAX_SLEEP_MINUTES
+                ));
+            }
+            SleepTimer::Minutes { minutes, .. } => {
+                Some(Instant::now() + Duration::from_secs(60 * minutes))
+            }
+            SleepTimer::AfterTrack | SleepTimer::AfterAlbum => None,
+        };
+        *self.sleep_timer.lock() = Some((timer, deadline));
+        // we might have faded out already
+        self.apply_volume();
+        Ok(())
+    }
+
+    /// cancels the sleep timer and restores the volume it faded out
+    pub(crate) fn cancel_sleep_timer(&self) {
+        self.sleep_timer.lock().take();
+        self.apply_volume();
+    }
+
+    /// returns the running sleep timer
+    pub(crate) fn get_sleep_timer(&self) -> Option<SleepTimerJson> {
+        let (timer, deadline) = (*self.sleep_timer.lock())?;
+        Some(SleepTimerJson {
+            timer,
+            remaining: deadline.map(|d| d.saturating_duration_since(Instant::now()).as_secs()),
+        })
+    }
+
+    /// Fades out and stops when the sleep timer ends, needs to be called regularly.
+    /// Returns the running sleep timer.
+    pub(crate) fn check_sleep_timer(&self) -> Option<SleepTimerJson> {
+        let (timer, deadline) = (*self.sleep_timer.lock())?;
+        let Some(deadline) = deadline else {
+            return self.get_sleep_timer();
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            info!("Sleep timer stops playback");
+            self.sleep_timer.lock().take();
+            self.do_gstreamer_action(GStreamerAction::Stop);
+            self.apply_volume();
+            return None;
+        }
+        if matches!(timer, SleepTimer::Minutes { fade: true, .. }) && remaining < SLEEP_FADE {
+            self.apply_volume();
+        }
+        self.get_sleep_timer()
+    }
+
+    /// how much the sleep timer turned the volume down, 1.0 if it does not fade out yet
+    fn sleep_fade(&self) -> f64 {
+        match *self.sleep_timer.lock() {
+            Some((SleepTimer::Minutes { fade: true, .. }, Some(deadline))) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                (remaining.as_secs_f64() / SLEEP_FADE.as_secs_f64()).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// the volume element of the output, it only changes what the sink plays
+    fn output_volume(&self) -> gstreamer::Element {
+        self.output_pipeline
+            .by_name("volume")
+            .expect("Output without volume")
+    }
+
+    /// Sets our volume on the output, faded by the sleep timer.
+    /// A crossfade fades with the mixer, so they do not overwrite each other.
+    fn apply_volume(&self) {
+        let volume = *self.volume.lock() * self.sleep_fade();
+        self.output_volume().set_property("volume", volume);
+    }
+
+    /// Returns the uri of the track we should play next, so `player` can play it without a gap.
+    /// Returns None if we want to decide at the track boundary, i.e., if we changed tabs, crossfade, the next file is missing
+    /// or has a bookmark we start at.
+    fn gstreamer_about_to_finish(&self, player: &gstreamer::Element) -> Option<String> {
+        if player != self.element() {
+            return None;
+        }
+        let next = self.upcoming()?;
+        if self.crossfade_wanted(next) {
+            return None;
+        }
+        if !self.current_playlist.is_available_at(next) || self.bookmark(next).is_some() {
+            return None;
+        }
+        let uri = self.current_playlist.get_uri_at(next)?;
+        info!("Queueing {} for gapless playback", uri);
+        *self.queued.lock() = Some(next);
+        Some(uri)
+    }
+
+    /// Do we crossfade into `next`? We do not crossfade between tracks of the same album, i.e., to keep live albums gapless.
+    fn crossfade_wanted(&self, next: usize) -> bool {
+        if self.crossfade.load(Ordering::SeqCst) == 0 {
+            return false;
+        }
+        let Some(current) = self
+            .current_playlist
+            .get_track_at(self.current_playlist.current_position())
+        else {
+            return false;
+        };
+        self.current_playlist
+            .get_track_at(next)
+            .is_some_and(|t| t.album.is_empty() || t.album != current.album)
+    }
+
+    /// Starts a crossfade if the current track ends in less than the crossfade duration
+    fn check_crossfade(&self) {
+        let crossfade = self.crossfade.load(Ordering::SeqCst);
+        if crossfade == 0
+            || self.queued.lock().is_some()
+            || self.get_state() != GStreamerMessage::Playing
+        {
+            return;
+        }
+        let (Some(position), Some(duration)) = (
+            self.element().query_position::<gstreamer::ClockTime>(),
+            self.element().query_duration::<gstreamer::ClockTime>(),
+        ) else {
+            return;
+        };
+        // we never fade longer than half of the track, so short tracks do not get skipped
+        let fade = std::cmp::min(gstreamer::ClockTime::from_seconds(crossfade), duration / 2);
+        if duration.saturating_sub(position) > fade {
+            return;
+        }
+        if self
+            .upcoming()
+            .is_some_and(|next| self.crossfade_wanted(next))
+        {
+            if let Some(i) = self.finish_track() {
+                self.crossfade_to(i, Duration::from_nanos(fade.nseconds()));
+            }
+        }
+    }
+
+    /// Plays track `i` on the inactive player and fades it in over `fade` while the current player fades out
+    fn crossfade_to(&self, i: usize, fade: Duration) {
+        self.stop_fade();
+        self.queued.lock().take();
+        self.current_playlist.set(i);
+        self.apply_replaygain();
+        let (Some(uri), Some(path)) = (
+            self.current_playlist.get_current_uri(),
+            self.current_playlist.get_current_path(),
+        ) else {
+            return self.do_gstreamer_action(GStreamerAction::Play(i));
+        };
+        if !self.current_playlist.is_available_at(i) || self.bookmark(i).is_some() {
+            // play handles missing files and starting at a bookmark
+            return self.do_gstreamer_action(GStreamerAction::Play(i));
+        }
+        info!("Crossfading to uri: {:?}", path);
+
+        let old_index = self.active.load(Ordering::SeqCst);
+        let old = self.element().clone();
+        let new = self.inactive_element().clone();
+        let old_pad = self.mixer_pads[old_index].clone();
+        let new_pad = self.mixer_pads[1 - old_index].clone();
+        set_state(&new, gstreamer::State::Ready);
+        new_pad.set_property("volume", 0.0_f64);
+        new.set_property("uri", uri);
+        set_state(&new, gstreamer::State::Playing);
+        self.active.fetch_xor(1, Ordering::SeqCst);
+        self.sync_output();
+
+        let id = self.fade_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let fade_id = self.fade_id.clone();
+        tokio::spawn(async move {
+            let steps = std::cmp::max(1, fade.as_millis() / CROSSFADE_STEP.as_millis());
+            for step in 1..=steps {
+                tokio::time::sleep(CROSSFADE_STEP).await;
+                if fade_id.load(Ordering::SeqCst) != id {
+                    return;
+                }
+                let fraction = step as f64 / steps as f64;
+                new_pad.set_property("volume", fraction);
+                old_pad.set_property("volume", 1.0 - fraction);
+            }
+            if fade_id.load(Ordering::SeqCst) == id {
+                set_state(&old, gstreamer::State::Ready);
+                old_pad.set_property("volume", 1.0_f64);
+            }
+        });
+
+        if let Err(e) = self.sender.send(GStreamerAction::Play(i).into()) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+    }
+
+    /// Stops a running crossfade, the player fading out stops and the active one plays with the full volume
+    fn stop_fade(&self) {
+        self.fade_id.fetch_add(1, Ordering::SeqCst);
+        set_state(self.inactive_element(), gstreamer::State::Ready);
+        for pad in &self.mixer_pads {
+            pad.set_property("volume", 1.0_f64);
+        }
+    }
+
+    /// returns the volume in percent and if we are muted
+    pub(crate) fn get_volume(&self) -> VolumeJson {
+        VolumeJson {
+            volume: (*self.volume.lock() * 100.0).round() as u32,
+            muted: self.output_volume().property::<bool>("mute"),
+        }
+    }
+
+    /// informs everyone listening that the volume changed
+    fn send_volume(&self) {
+        if let Err(e) = self
+            .sender
+            .send(GStreamerMessage::VolumeChanged(self.get_volume()))
+        {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+    }
+
+    /// returns the element called `name` in the audio sink bin of every player
+    fn sink_elements(&self, name: &str) -> Vec<gstreamer::Element> {
+        self.players
+            .iter()
+            .filter_map(|p| sink_element(p, name))
+            .collect()
+    }
+
+    /// sets the equalizer gains on all players
+    fn apply_equalizer_gains(&self, gains: [f64; equalizer::BANDS]) {
+        let gains = equalizer::clamp_gains(gains);
+        for eq in self.sink_elements("equalizer") {
+            for (band, gain) in gains.iter().enumerate() {
+                eq.set_property(&format!("band{}", band), *gain);
+            }
+        }
+    }
+
+    /// the current equalizer gains
+    fn equalizer_gains(&self) -> [f64; equalizer::BANDS] {
+        let mut gains = [0.0; equalizer::BANDS];
+        if let Some(eq) = self.sink_elements("equalizer").first() {
+            for (band, gain) in gains.iter_mut().enumerate() {
+                *gain = eq.property::<f64>(&format!("band{}", band));
+            }
+        }
+        gains
+    }
+
+    /// saves the equalizer state, so we restore it on startup
+    fn save_equalizer(&self) {
+        let preset = self.equalizer_preset.lock().clone().unwrap_or_default();
+        let gains = self
+            .equalizer_gains()
+            .iter()
+            .map(f64::to_string)
+            .collect::<Vec<String>>()
+            .join(",");
+        if let Err(e) = crate::utils::save_preference("equalizer", preset)
+            .and_then(|()| crate::utils::save_preference("equalizer_gains", gains))
+        {
+            warn!("Could not save equalizer: {}", e);
+        }
+    }
+
+    /// returns the equalizer gains, the active preset and all presets
+    pub(crate) fn get_equalizer(&self) -> EqualizerJson {
+        EqualizerJson {
+            preset: self.equalizer_preset.lock().clone(),
+            gains: self.equalizer_gains(),
+            presets: equalizer::presets(),
+        }
+    }
+
+    /// sets the equalizer gains by hand, which deselects the active preset
+    pub(crate) fn set_equalizer_gains(&self, gains: [f64; equalizer::BANDS]) {
+        self.apply_equalizer_gains(gains);
+        self.equalizer_preset.lock().take();
+        self.save_equalizer();
+    }
+
+    /// activates the preset called `name`
+    pub(crate) fn set_equalizer_preset(&self, name: &str) -> Result<(), String> {
+        let preset =
+            equalizer::find(name).ok_or_else(|| format!("Could not find preset {}", name))?;
+        self.apply_equalizer_gains(preset.gains);
+        *self.equalizer_preset.lock() = Some(preset.name);
+        self.save_equalizer();
+        Ok(())
+    }
+
+    /// saves the current gains as the preset `name` and activates it
+    pub(crate) fn save_equalizer_preset(&self, name: String) -> Result<(), String> {
+        equalizer::save(EqualizerPreset {
+            name: name.clone(),
+            gains: self.equalizer_gains(),
+        })?;
+        *self.equalizer_preset.lock() = Some(name);
+        self.save_equalizer();
+        Ok(())
+    }
+
+    /// deletes the saved preset `name`, the gains stay as they are if it was active
+    pub(crate) fn delete_equalizer_preset(&self, name: &str) -> Result<(), String> {
+        equalizer::delete(name)?;
+        let active = self.equalizer_preset.lock().as_deref() == Some(name);
+        if active {
+            self.equalizer_preset.lock().take();
+            self.save_equalizer();
+        }
+        Ok(())
+    }
+
+    /// Do we play an album in order, i.e., does a neighbouring track in the playlist have the same album
+    fn plays_album(&self) -> bool {
+        let pos = self.current_playlist.current_position();
+        let Some(current) = self.current_playlist.get_track_at(pos) else {
+            return false;
+        };
+        match self.get_play_mode() {
+            PlayMode::Shuffle => return false,
+            // album shuffle always plays whole albums
+            PlayMode::AlbumShuffle => return !current.album.is_empty(),
+            PlayMode::Ordered => {}
+        }
+        !current.album.is_empty()
+            && [pos.checked_sub(1), pos.checked_add(1)]
+                .into_iter()
+                .flatten()
+                .filter_map(|i| self.current_playlist.get_track_at(i))
+                .any(|t| t.album == current.album)
+    }
+
+    /// sets the ReplayGain settings on rgvolume and rglimiter, needs to be called on track changes for the auto mode
+    fn apply_replaygain(&self) {
+        let replaygain = *self.replaygain.lock();
+        let off = replaygain.mode == ReplayGainMode::Off;
+        let album_mode = match replaygain.mode {
+            ReplayGainMode::Album => true,
+            ReplayGainMode::Off | ReplayGainMode::Track => false,
+            ReplayGainMode::Auto => self.plays_album(),
+        };
+        for rgvolume in self.sink_elements("rgvolume") {
+            rgvolume.set_property("album-mode", album_mode);
+            rgvolume.set_property("pre-amp", if off { 0.0 } else { replaygain.preamp });
+            rgvolume.set_property(
+                "fallback-gain",
+                if off { 0.0 } else { replaygain.fallback_gain },
+            );
+        }
+        for rglimiter in self.sink_elements("rglimiter") {
+            rglimiter.set_property("enabled", !off);
+        }
+    }
+
+    /// returns the ReplayGain settings
+    pub(crate) fn get_replaygain(&self) -> ReplayGainJson {
+        *self.replaygain.lock()
+    }
+
+    /// sets the ReplayGain settings, applies them to the playing track and saves them in the preferences
+    pub(crate) fn set_replaygain(&self, replaygain: ReplayGainJson) {
+        let old = std::mem::replace(&mut *self.replaygain.lock(), replaygain);
+        self.apply_replaygain();
+
+        // rgvolume keeps the gain of the tags it has seen, so we override them for the playing track
+        let was_off = old.mode == ReplayGainMode::Off;
+        let is_off = replaygain.mode == ReplayGainMode::Off;
+        if was_off != is_off {
+            for (player, tags) in self.players.iter().zip(&self.replaygain_tags) {
+                let tags = if is_off {
+                    Some(zero_replaygain_tags())
+                } else {
+                    tags.lock().clone()
+                };
+                if let (Some(tags), Some(pad)) = (
+                    tags,
+                    sink_element(player, "rgvolume").and_then(|rg| rg.static_pad("sink")),
+                ) {
+                    pad.send_event(gstreamer::event::Tag::new(tags));
+                }
+            }
+        }
+
+        save_json_preference("replaygain", &replaygain);
+    }
+
+    /// returns the output we play to
+    pub(crate) fn get_output(&self) -> AudioOutput {
+        self.output.lock().clone()
+    }
+
+    /// Switches the output we play to and saves it in the preferences.
+    /// The players keep playing, only the sink of the output changes.
+    pub(crate) fn set_output(&self, output: AudioOutput) -> Result<(), String> {
+        // we create the sink first, so a bad output leaves everything as it is
+        let sink = output::make_sink(&output)?;
+        {
+            let mut drain = self.drain.lock();
+            if self.output_pipeline.current_state() == gstreamer::State::Playing {
+                // the old sink plays out first, `finish_drain` switches to the new one
+                *drain = Some(self.drain_output(Some(sink)));
+            } else {
+                drain.take();
+                self.output_pipeline
+                    .set_state(gstreamer::State::Ready)
+                    .map_err(|e| format!("Could not stop output: {}", e))?;
+                replace_sink(&self.output_pipeline, sink)?;
+            }
+        }
+        self.sync_output();
+
+        let json = serde_json::to_string(&output).map_err(|e| e.to_string())?;
+        *self.output.lock() = output;
+        crate::utils::save_preference("output", json)
+    }
+
+    /// seeks to `position` and plays with our playback rate from there
+    fn seek_with_rate(
+        &self,
+        position: gstreamer::ClockTime,
+    ) -> Result<(), gstreamer::glib::BoolError> {
+        self.element().seek(
+            *self.rate.lock(),
+            gstreamer::SeekFlags::FLUSH | gstreamer::SeekFlags::ACCURATE,
+            gstreamer::SeekType::Set,
+            position,
+            gstreamer::SeekType::End,
+            gstreamer::ClockTime::ZERO,
+        )
+    }
+
+    /// seeks with our playback rate if the playing stream has a different one
+    fn check_rate(&self) {
+        if self.get_state() != GStreamerMessage::Playing {
+            return;
+        }
+        let rate = *self.rate.lock();
+        let mut query = gstreamer::query::Segment::new(gstreamer::Format::Time);
+        if !self.element().query(&mut query) || (query.result().0 - rate).abs() < f64::EPSILON {
+            return;
+        }
+        if let Some(position) = self.element().query_position::<gstreamer::ClockTime>() {
+            if let Err(e) = self.seek_with_rate(position) {
+                warn!("Could not set playback rate: {}", e);
+            }
+        }
+    }
+
+    /// returns the playback rate
+    pub(crate) fn get_rate(&self) -> f64 {
+        *self.rate.lock()
+    }
+
+    /// sets the playback rate, clamped to our bounds, and saves it in the preferences
+    pub(crate) fn set_rate(&self, rate: f64) {
+        if !rate.is_finite() {
+            return;
+        }
+        let rate = rate.clamp(MIN_RATE, MAX_RATE);
+        *self.rate.lock() = rate;
+        self.check_rate();
+        if let Err(e) = crate::utils::save_preference("rate", rate.to_string()) {
+            warn!("Could not save rate: {}", e);
+        }
+        if let Err(e) = self.sender.send(GStreamerMessage::RateChanged) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+    }
+
+    /// returns the crossfade duration in seconds, zero means disabled
+    pub(crate) fn get_crossfade(&self) -> u64 {
+        self.crossfade.load(Ordering::SeqCst)
+    }
+
+    /// sets the crossfade duration in seconds and saves it in the preferences, zero disables crossfading
+    pub(crate) fn set_crossfade(&self, secs: u64) {
+        self.crossfade.store(secs, Ordering::SeqCst);
+        if let Err(e) = crate::utils::save_preference("crossfade", secs.to_string()) {
+            warn!("Could not save crossfade: {}", e);
+        }
+    }
+
+    /// Handle if gstreamer starts a new stream, which is the real track boundary for a track we queued in about-to-finish
+    fn gstreamer_handle_stream_start(&self) {
+        // a new stream announces its own title
+        self.set_stream_title(None);
+        let queued = self.queued.lock().take();
+        if let Some(queued) = queued {
+            info!("Handling gapless track change");
+            match self.finish_track() {
+                Some(i) if i == queued => {
+                    self.current_playlist.set(i);
+                    self.apply_replaygain();
+                    if let Err(e) = self.sender.send(GStreamerAction::Play(i).into()) {
+                        warn!("Could not broadcast, ignoring: {}", e);
+                    }
+                }
+                // something changed since we queued the track, so we restart with the correct one
+                Some(i) => self.do_gstreamer_action(GStreamerAction::Play(i)),
+                None => self.do_gstreamer_action(GStreamerAction::Stop),
+            }
+        }
+    }
+
+    /// Updates the playcount of the track that just finished and returns the position we should play next.
+    /// Returns None if we should stop.
+    fn finish_track(&self) -> Option<usize> {
+        use crate::db::UpdatePlayCount;
 
         PlaylistTabsExt::update_current_playcount(&self.current_playlist);
 
         //we want to separately update the playcount in the database because we never want to miss if something was played
         let mut old_track = self.current_playlist.get_current_track();
+        // the track played to the end, so there is nothing to remember
+        let tab = self
+            .current_playlist
+            .tab_name(self.current_playlist.current_playing_in())
+            .unwrap_or_default();
+        if self.bookmark_rules.applies(&old_track, &tab) {
+            if let Err(e) = bookmarks::remove(&self.pool, old_track.id) {
+                warn!("{}", e);
+            }
+        }
         let pc = self.pool.clone();
         tokio::spawn(async move {
             old_track.update_playcount(pc);
@@ -278,32 +2112,35 @@ impl GStreamer {
 
         if self.current_playlist.current_tab() != self.current_playlist.current_playing_in() {
             info!("Stopping because different playlist");
-            self.do_gstreamer_action(GStreamerAction::Stop);
+            None
         } else {
-            self.sender
-                .send(GStreamerMessage::IncreasePlayCount(
-                    self.current_playlist.current_position(),
-                ))
-                .expect("Error in sending gstreamer message");
+            // the track played through, so the tracks are fine again
+            self.failures.store(0, Ordering::SeqCst);
+            if let Err(e) = self.sender.send(GStreamerMessage::IncreasePlayCount(
+                self.current_playlist.current_position(),
+            )) {
+                warn!("Could not broadcast, ignoring: {}", e);
+            }
 
-            let res = if self.repeat_once.load(Ordering::Acquire) {
+            let next = self.peek_following(true);
+            let sleeping = self.sleeps_before(next);
+            if self.repeat_once.swap(false, Ordering::SeqCst) {
                 info!("we are repeat playing");
-                self.repeat_once.store(false, Ordering::SeqCst);
-                Some(self.current_playlist.current_position())
-            } else {
-                self.current_playlist.next_or_eol()
-            };
-            if let Some(i) = res {
-                self.do_gstreamer_action(GStreamerAction::Play(i));
+            }
+            let next = self.advance_to(next);
+            if sleeping {
+                info!("Sleep timer stops playback");
+                self.sleep_timer.lock().take();
+                None
             } else {
-                self.do_gstreamer_action(GStreamerAction::Stop);
+                next
             }
         }
     }
 
     /// return the gstreamer state in a custom type
     pub(crate) fn get_state(&self) -> viola_common::GStreamerMessage {
-        match self.element.state(gstreamer::ClockTime::SECOND).1 {
+        match self.element().state(gstreamer::ClockTime::SECOND).1 {
             gstreamer::State::VoidPending | gstreamer::State::Null | gstreamer::State::Ready => {
                 GStreamerMessage::Stopped
             }
@@ -312,9 +2149,152 @@ impl GStreamer {
         }
     }
 
+    /// the position in the current track in microseconds
+    pub(crate) fn get_position(&self) -> Option<u64> {
+        self.element()
+            .query_position::<gstreamer::ClockTime>()
+            .map(gstreamer::ClockTime::useconds)
+    }
+
+    /// seeks to `position` microseconds into the current track and tells everyone about it
+    fn seek_to(&self, position: u64) {
+        if let Err(e) = self.seek_with_rate(gstreamer::ClockTime::from_useconds(position)) {
+            warn!("Could not seek: {}", e);
+        } else if let Err(e) = self.sender.send(GStreamerMessage::Seeked(position)) {
+            warn!("Could not broadcast, ignoring: {}", e);
+        }
+    }
+
     /// how many seconds are elapsed
     pub(crate) fn get_elapsed(&self) -> Option<u64> {
-        let cltime_opt: Option<gstreamer::ClockTime> = self.element.query_position();
+        let cltime_opt: Option<gstreamer::ClockTime> = self.element().query_position();
         cltime_opt.map(gstreamer::ClockTime::seconds)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::loaded_playlist::LoadedPlaylist;
+    use std::path::Path;
+    use viola_common::Track;
+
+    fn track(id: i32, path: &Path) -> Track {
+        Track {
+            id,
+            title: format!("Tone {}", id),
+            artist: String::from("Viola"),
+            album: String::from("Tones"),
+            genre: String::new(),
+            tracknumber: Some(id),
+            year: None,
+            path: path.to_string_lossy().into_owned(),
+            length: 1,
+            albumpath: None,
+            playcount: None,
+        }
+    }
+
+    /// a player with three tones of `dir` in its tab that plays into the null output
+    fn tone_player(
+        dir: &Path,
+    ) -> (
+        Arc<GStreamer>,
+        tokio::sync::broadcast::Receiver<GStreamerMessage>,
+    ) {
+        gstreamer::init().unwrap();
+        let items = (1..=3)
+            .map(|id| {
+                let path = dir.join(format!("{}.wav", id));
+                crate::mix::write_tone(&path);
+                track(id, &path)
+            })
+            .collect();
+        let tabs = crate::playlist_tabs::from_playlists(vec![LoadedPlaylist {
+            id: 0,
+            name: String::from("Tones"),
+            items,
+            current_position: 0,
+        }]);
+        let mut prefs = PreferencesMap::new();
+        prefs.insert(
+            String::from("output"),
+            serde_json::to_string(&AudioOutput::Null).unwrap(),
+        );
+        let (sender, receiver) = tokio::sync::broadcast::channel(1024);
+        let gst = with_preferences(
+            tabs,
+            crate::db::test_db(),
+            sender,
+            &prefs,
+            None,
+            BookmarkRules::default(),
+        )
+        .unwrap();
+        (gst, receiver)
+    }
+
+    /// runs `test` with a temporary dir, the runtime does not wait for the bus threads of the players as they never end
+    fn with_runtime<F: std::future::Future<Output = ()>>(
+        name: &str,
+        test: impl FnOnce(PathBuf) -> F,
+    ) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("viola-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        runtime.block_on(test(dir.clone()));
+        std::fs::remove_dir_all(dir).unwrap();
+        runtime.shutdown_timeout(Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_plays_to_the_end() {
+        with_runtime("play", |dir| async move {
+            let (gst, mut receiver) = tone_player(&dir);
+            gst.do_gstreamer_action(GStreamerAction::Play(0));
+            let mut played = Vec::new();
+            tokio::time::timeout(Duration::from_secs(30), async {
+                loop {
+                    match receiver.recv().await.unwrap() {
+                        GStreamerMessage::IncreasePlayCount(i) => played.push(i),
+                        GStreamerMessage::Stopped => break,
+                        _ => {}
+                    }
+                }
+            })
+            .await
+            .expect("The playlist did not play to the end");
+            assert_eq!(played, vec![0, 1, 2]);
+            assert_eq!(gst.get_state(), GStreamerMessage::Stopped);
+            // the output stops once it played out
+            tokio::time::timeout(Duration::from_secs(2), async {
+                while gst.drain.lock().is_some() {
+                    tokio::time::sleep(PLAYBACK_CHECK).await;
+                }
+            })
+            .await
+            .expect("The output did not play out");
+            assert_eq!(gst.output_pipeline.current_state(), gstreamer::State::Ready);
+        });
+    }
+
+    #[test]
+    fn test_output_failed() {
+        with_runtime("output", |dir| async move {
+            let (gst, _receiver) = tone_player(&dir);
+            gst.do_gstreamer_action(GStreamerAction::Play(1));
+            assert_eq!(gst.get_state(), GStreamerMessage::Playing);
+
+            gst.output_failed(String::from("The device went away"));
+            assert_eq!(gst.get_state(), GStreamerMessage::Stopped);
+            assert_eq!(gst.output_pipeline.current_state(), gstreamer::State::Ready);
+            // the track is fine, so we neither skip it nor count it as failed
+            assert_eq!(gst.current_playlist.current_position(), 1);
+            assert_eq!(gst.failures.load(Ordering::SeqCst), 0);
+            let errors = gst.errors.lock();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].path, None);
+            assert!(errors[0].error.contains("Null"));
+        });
+    }
+}
@@ -0,0 +1,252 @@
+use log::warn;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use viola_common::MixFormat;
+
+use crate::mix;
+
+/// bytes of audio between two ICY metadata blocks
+pub(crate) const ICY_METAINT: usize = 16000;
+/// how many encoded buffers a listener may fall behind before it skips some
+const LISTENER_BACKLOG: usize = 256;
+/// ICY metadata blocks are at most 255 times this long
+const ICY_BLOCK: usize = 16;
+
+/// The header buffers the encoder sent last, a listener needs them before it can decode the stream
+#[derive(Debug, Default)]
+struct Headers {
+    buffers: Vec<Vec<u8>>,
+    /// did a data buffer follow the headers, so the next header buffer belongs to a new stream
+    complete: bool,
+}
+
+/// Serves what we play, encoded by a branch of the output pipeline, to any number of listeners
+#[derive(Debug)]
+pub(crate) struct LiveStream {
+    format: MixFormat,
+    sender: broadcast::Sender<Vec<u8>>,
+    headers: Mutex<Headers>,
+    listeners: Arc<AtomicUsize>,
+}
+
+impl LiveStream {
+    pub(crate) fn new(format: MixFormat) -> Result<Self, String> {
+        if format == MixFormat::Flac {
+            return Err(String::from("We can only stream Opus or MP3"));
+        }
+        Ok(LiveStream {
+            format,
+            sender: broadcast::channel(LISTENER_BACKLOG).0,
+            headers: Default::default(),
+            listeners: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// the elements of the branch in the output pipeline that encode the stream, they end in an appsink called `streamsink`
+    pub(crate) fn branch_description(&self) -> String {
+        format!(
+            "queue name=streamqueue leaky=downstream ! audioconvert ! audioresample ! {} ! appsink name=streamsink sync=false async=false emit-signals=true",
+            mix::encoder_description(self.format)
+        )
+    }
+
+    /// the content type of the stream
+    pub(crate) fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    /// does anybody listen, the encoder gets no audio otherwise
+    pub(crate) fn has_listeners(&self) -> bool {
+        self.listeners.load(Ordering::SeqCst) > 0
+    }
+
+    /// takes an encoded buffer and sends it to the listeners
+    pub(crate) fn push(&self, data: Vec<u8>, header: bool) {
+        let mut headers = self.headers.lock();
+        if header {
+            if headers.complete {
+                headers.buffers.clear();
+                headers.complete = false;
+            }
+            headers.buffers.push(data.clone());
+        } else {
+            headers.complete = true;
+        }
+        // sending only fails without listeners
+        let _ = self.sender.send(data);
+    }
+
+    /// a new listener that starts with the headers of the stream
+    pub(crate) fn subscribe(&self) -> Listener {
+        let headers = self.headers.lock();
+        let headers = headers.buffers.iter().cloned().collect();
+        self.listeners.fetch_add(1, Ordering::SeqCst);
+        Listener {
+            headers,
+            receiver: self.sender.subscribe(),
+            listeners: self.listeners.clone(),
+        }
+    }
+}
+
+/// Receives the stream for one client
+pub(crate) struct Listener {
+    headers: VecDeque<Vec<u8>>,
+    receiver: broadcast::Receiver<Vec<u8>>,
+    listeners: Arc<AtomicUsize>,
+}
+
+impl Listener {
+    /// the next buffer of the stream, a listener that falls behind skips buffers
+    pub(crate) async fn next(&mut self) -> Option<Vec<u8>> {
+        if let Some(header) = self.headers.pop_front() {
+            return Some(header);
+        }
+        loop {
+            match self.receiver.recv().await {
+                Ok(data) => return Some(data),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Listener skipped {} buffers", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.listeners.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Puts ICY metadata into the stream of one listener every `ICY_METAINT` bytes
+#[derive(Debug)]
+pub(crate) struct IcyInserter {
+    /// bytes until the next metadata block
+    remaining: usize,
+    /// the title we sent last
+    title: Option<String>,
+}
+
+impl IcyInserter {
+    pub(crate) fn new() -> Self {
+        IcyInserter {
+            remaining: ICY_METAINT,
+            title: None,
+        }
+    }
+
+    /// returns `data` with the metadata blocks it needs, a block only has the title if it changed
+    pub(crate) fn insert(&mut self, data: &[u8], title: &str) -> Vec<u8> {
+        let mut res = Vec::with_capacity(data.len() + 1);
+        let mut rest = data;
+        while rest.len() >= self.remaining {
+            let (before, after) = rest.split_at(self.remaining);
+            res.extend_from_slice(before);
+            if self.title.as_deref() == Some(title) {
+                res.push(0);
+            } else {
+                res.extend(icy_block(title));
+                self.title = Some(title.to_string());
+            }
+            self.remaining = ICY_METAINT;
+            rest = after;
+        }
+        res.extend_from_slice(rest);
+        self.remaining -= rest.len();
+        res
+    }
+}
+
+/// an ICY metadata block announcing `title`
+fn icy_block(title: &str) -> Vec<u8> {
+    let mut meta = format!("StreamTitle='{}';", title.replace('\'', "’")).into_bytes();
+    meta.truncate(255 * ICY_BLOCK);
+    let blocks = meta.len().div_ceil(ICY_BLOCK);
+    meta.resize(blocks * ICY_BLOCK, 0);
+    let mut res = Vec::with_capacity(meta.len() + 1);
+    res.push(blocks as u8);
+    res.extend(meta);
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn received(listener: &mut Listener) -> Vec<Vec<u8>> {
+        let mut res: Vec<Vec<u8>> = listener.headers.drain(..).collect();
+        while let Ok(data) = listener.receiver.try_recv() {
+            res.push(data);
+        }
+        res
+    }
+
+    #[test]
+    fn test_headers() {
+        let stream = LiveStream::new(MixFormat::Opus).unwrap();
+        let mut early = stream.subscribe();
+        stream.push(vec![1], true);
+        stream.push(vec![2], false);
+        let mut late = stream.subscribe();
+        // the encoder restarts with new headers
+        stream.push(vec![3], true);
+        stream.push(vec![4], false);
+
+        let mut latest = stream.subscribe();
+        assert!(stream.has_listeners());
+        stream.push(vec![5], false);
+        assert_eq!(
+            received(&mut early),
+            vec![vec![1], vec![2], vec![3], vec![4], vec![5]]
+        );
+        assert_eq!(
+            received(&mut late),
+            vec![vec![1], vec![3], vec![4], vec![5]]
+        );
+        assert_eq!(received(&mut latest), vec![vec![3], vec![5]]);
+
+        drop(early);
+        drop(late);
+        drop(latest);
+        assert!(!stream.has_listeners());
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            LiveStream::new(MixFormat::Opus).unwrap().content_type(),
+            "audio/ogg"
+        );
+        assert_eq!(
+            LiveStream::new(MixFormat::Mp3).unwrap().content_type(),
+            "audio/mpeg"
+        );
+        assert!(LiveStream::new(MixFormat::Flac).is_err());
+        assert_eq!(MixFormat::Flac.content_type(), "audio/flac");
+    }
+
+    #[test]
+    fn test_icy() {
+        let mut icy = IcyInserter::new();
+        let data = vec![7; ICY_METAINT + 10];
+        let res = icy.insert(&data, "Artist - Title");
+        let block = icy_block("Artist - Title");
+        assert_eq!(block[0], 2);
+        assert_eq!(block.len(), 33);
+        assert_eq!(res.len(), data.len() + block.len());
+        assert_eq!(
+            &res[ICY_METAINT..ICY_METAINT + block.len()],
+            block.as_slice()
+        );
+
+        // the same title gets an empty block
+        let res = icy.insert(&vec![7; ICY_METAINT - 10], "Artist - Title");
+        assert_eq!(res.len(), ICY_METAINT - 10 + 1);
+        assert_eq!(res[ICY_METAINT - 10], 0);
+    }
+}
@@ -3,7 +3,7 @@ use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use serde::Serialize;
 use std::path::PathBuf;
 
-use crate::playlist::{NewPlaylist, NewPlaylistTrack, Playlist};
+use crate::playlist::{NewPlaylist, NewPlaylistStation, NewPlaylistTrack, Playlist};
 use crate::types::LoadedPlaylistPtr;
 use viola_common::Track;
 const FRAGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'#');
@@ -88,6 +88,7 @@ impl SavePlaylistExt for LoadedPlaylistPtr {
     fn save(&self, db: &mut diesel::SqliteConnection) -> Result<(), diesel::result::Error> {
         use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
         use viola_common::schema::playlists::dsl::*;
+        use viola_common::schema::playliststations;
         use viola_common::schema::playlisttracks::dsl::*;
 
         info!("playlist id {:?}", self.id);
@@ -121,6 +122,9 @@ impl SavePlaylistExt for LoadedPlaylistPtr {
         diesel::delete(playlisttracks)
             .filter(playlist_id.eq(playlist.id))
             .execute(db)?;
+        diesel::delete(playliststations::table)
+            .filter(playliststations::playlist_id.eq(playlist.id))
+            .execute(db)?;
 
         //inserting new tracks
         info!("starting to gather");
@@ -128,10 +132,12 @@ impl SavePlaylistExt for LoadedPlaylistPtr {
             .items
             .iter()
             .enumerate()
-            .map(|(index, track)| NewPlaylistTrack {
-                playlist_id: playlist.id,
-                track_id: track.id,
-                playlist_order: index as i32,
+            .filter_map(|(index, track)| {
+                track.library_id().map(|track_id| NewPlaylistTrack {
+                    playlist_id: playlist.id,
+                    track_id,
+                    playlist_order: index as i32,
+                })
             })
             .collect::<Vec<NewPlaylistTrack>>();
         info!("collected and inserting");
@@ -140,6 +146,23 @@ impl SavePlaylistExt for LoadedPlaylistPtr {
             .values(&vals)
             .execute(db)?;
 
+        // stations keep their position in the playlist between the tracks
+        let station_vals = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, track)| {
+                track.station_id().map(|station_id| NewPlaylistStation {
+                    playlist_id: playlist.id,
+                    station_id,
+                    playlist_order: index as i32,
+                })
+            })
+            .collect::<Vec<NewPlaylistStation>>();
+        diesel::insert_into(playliststations::table)
+            .values(&station_vals)
+            .execute(db)?;
+
         info!("done");
 
         Ok(())
@@ -160,25 +183,24 @@ pub(crate) trait PlaylistControls {
     fn delete_range(&mut self, _: std::ops::Range<usize>);
     /// sets the position to the next one or zero if we are eol. Returns None if we are eol otherwise the position.
     fn next_or_eol(&mut self) -> Option<usize>;
+    /// returns the position `next_or_eol` would go to without changing anything
+    fn peek_next(&self) -> Option<usize>;
+    /// Get track path at position `i`
+    fn get_path_at(&self, i: usize) -> Option<PathBuf>;
+    /// Get track uri at position `i`
+    fn get_uri_at(&self, i: usize) -> Option<String>;
+    /// can we play the track at position `i`, i.e., it is a stream or the file exists
+    fn is_available_at(&self, i: usize) -> bool;
 }
 
 impl PlaylistControls for LoadedPlaylistPtr {
     fn get_current_path(&self) -> Option<PathBuf> {
-        let mut pb = PathBuf::new();
-        if let Some(t) = self.items.get(self.current_position) {
-            pb.push(t.path.clone());
-            Some(pb)
-        } else {
-            None
-        }
+        self.get_path_at(self.current_position)
     }
 
     fn get_current_uri(&self) -> Option<String> {
         info!("loading from playlist with name: {}", self.name);
-        self.items
-            .get(self.current_position)
-            .as_ref()
-            .map(|p| format!("file:////{}", utf8_percent_encode(&p.path, FRAGMENT)))
+        self.get_uri_at(self.current_position)
     }
 
     fn previous(&mut self) -> Option<usize> {
@@ -209,19 +231,101 @@ impl PlaylistControls for LoadedPlaylistPtr {
     }
 
     fn next_or_eol(&mut self) -> Option<usize> {
-        if self.items.len() == 1 {
-            None
-        } else {
-            let next_pos = {
-                self.current_position += 1 % (self.items.len() - 1);
-                self.current_position
-            };
+        let next_pos = self.peek_next();
+        self.current_position = next_pos.unwrap_or(0);
+        next_pos
+    }
+
+    fn peek_next(&self) -> Option<usize> {
+        Some(self.current_position + 1).filter(|i| *i < self.items.len())
+    }
+
+    fn get_path_at(&self, i: usize) -> Option<PathBuf> {
+        self.items.get(i).map(|t| PathBuf::from(&t.path))
+    }
 
-            if next_pos == 0 {
-                None
+    fn get_uri_at(&self, i: usize) -> Option<String> {
+        self.items.get(i).map(|p| {
+            if p.is_stream() {
+                p.path.clone()
             } else {
-                Some(next_pos)
+                format!("file:////{}", utf8_percent_encode(&p.path, FRAGMENT))
             }
+        })
+    }
+
+    fn is_available_at(&self, i: usize) -> bool {
+        self.items
+            .get(i)
+            .is_some_and(|t| t.is_stream() || std::path::Path::new(&t.path).exists())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playlist(length: usize) -> LoadedPlaylist {
+        let items = (0..length)
+            .map(|i| Track {
+                id: i as i32,
+                title: format!("Title {}", i),
+                artist: String::from("Artist"),
+                album: String::from("Album"),
+                genre: String::from("Genre"),
+                tracknumber: Some(i as i32),
+                year: None,
+                path: format!("/music/{}.mp3", i),
+                length: 1,
+                albumpath: None,
+                playcount: None,
+            })
+            .collect();
+        LoadedPlaylist {
+            id: 0,
+            name: String::from("Test"),
+            items,
+            current_position: 0,
         }
     }
+
+    #[test]
+    fn test_next_or_eol() {
+        let mut pl = playlist(3);
+        assert_eq!(pl.peek_next(), Some(1));
+        assert_eq!(pl.next_or_eol(), Some(1));
+        assert_eq!(pl.next_or_eol(), Some(2));
+        assert_eq!(pl.peek_next(), None);
+        assert_eq!(pl.next_or_eol(), None);
+        assert_eq!(pl.current_position, 0);
+    }
+
+    #[test]
+    fn test_stream_uri() {
+        let mut pl = playlist(2);
+        pl.items.push(Track::from(viola_common::Station {
+            id: 1,
+            name: String::from("Radio"),
+            url: String::from("https://example.org/radio.mp3"),
+        }));
+        assert_eq!(pl.get_uri_at(0), Some(String::from("file://///music/0.mp3")));
+        assert_eq!(
+            pl.get_uri_at(2),
+            Some(String::from("https://example.org/radio.mp3"))
+        );
+        assert!(!pl.is_available_at(0));
+        assert!(pl.is_available_at(2));
+        assert!(!pl.is_available_at(3));
+    }
+
+    #[test]
+    fn test_next_or_eol_short() {
+        let mut pl = playlist(2);
+        assert_eq!(pl.next_or_eol(), Some(1));
+        assert_eq!(pl.next_or_eol(), None);
+
+        let mut pl = playlist(1);
+        assert_eq!(pl.next_or_eol(), None);
+        assert_eq!(pl.current_position, 0);
+    }
 }
@@ -1,22 +1,30 @@
 #![recursion_limit = "4096"]
+pub mod bookmarks;
 pub mod db;
 pub mod dbus_interface;
+pub mod equalizer;
 pub mod gstreamer_wrapper;
 pub mod libraryviewstore;
+pub mod live_stream;
 pub mod loaded_playlist;
 pub mod maingui_web;
+pub mod mix;
 pub mod my_websocket;
+pub mod output;
+pub mod play_order;
 pub mod playlist;
 pub mod playlist_tabs;
 pub mod smartplaylist_parser;
+pub mod stations;
+pub mod track_server;
 pub mod types;
 pub mod utils;
 
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use log::info;
 use parking_lot::Mutex;
-use preferences::{prefs_base_dir, Preferences, PreferencesMap};
+use preferences::prefs_base_dir;
 use std::sync::Arc;
 use types::DBPool;
 
@@ -47,43 +55,97 @@ struct Args {
     /// Does not run the embedded webview
     #[clap(short, long)]
     webview: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Moves all paths in the database from one prefix to another without a rescan, i.e., if the music dir moved
+    Relocate {
+        /// prefix the paths currently start with
+        old_prefix: String,
+        /// prefix the paths should start with
+        new_prefix: String,
+    },
+    /// Renders a playlist tab into one file and writes a CUE sheet with the track boundaries next to it
+    Mix {
+        /// index of the tab
+        tab: usize,
+        /// file to write, gets the extension of the format if it has none
+        path: String,
+        /// flac, opus or mp3
+        #[clap(long, default_value = "flac")]
+        format: viola_common::MixFormat,
+        /// crossfade in seconds up to 30, zero joins the tracks gaplessly
+        #[clap(long, default_value_t = 0)]
+        crossfade: u64,
+    },
 }
 
 fn update_db(pool: &DBPool) -> Result<(), anyhow::Error> {
     info!("Updating Database");
-    let mut pref_reader =
-        crate::utils::get_config_file(&utils::ConfigWriteMode::Read).expect("No settings file");
-    let preferences = PreferencesMap::<String>::load_from(&mut pref_reader)
-        .context("Could not read settings file")?;
+    let preferences = utils::load_preferences();
     let music_dir = preferences
         .get("music_dir")
         .context("Could not get musicdir")?;
-    db::build_db(music_dir, pool, true).unwrap();
+    db::build_db(music_dir, pool, true, &|_| {}).map_err(anyhow::Error::msg)?;
     println!("creating m3u playlists");
     smartplaylist_parser::m3u_from_smartplaylist(music_dir, pool)?;
     Ok(())
 }
 
-fn update_db_fast(path: String, pool: &DBPool) {
+fn update_db_fast(path: String, pool: &DBPool) -> Result<(), anyhow::Error> {
     info!("Updating database with path {}", path);
     if !std::path::Path::new(&path).exists() {
-        println!("Path does not seem to exist");
+        bail!("Path {} does not seem to exist", path);
     }
-    db::build_db(&path, pool, false).unwrap();
+    db::build_db(&path, pool, false, &|_| {}).map_err(anyhow::Error::msg)?;
+    Ok(())
 }
 
 fn set_music_directory(new_music_dir: String) -> Result<(), anyhow::Error> {
-    let mut prefs = PreferencesMap::<String>::new();
-    prefs.insert(String::from("music_dir"), new_music_dir);
-    let mut prefs_file =
-        crate::utils::get_config_file(&utils::ConfigWriteMode::Write).expect("Cannot find config");
-    prefs
-        .save_to(&mut prefs_file)
+    utils::save_preference("music_dir", new_music_dir)
+        .map_err(anyhow::Error::msg)
         .context("Error in saving preferences")?;
     info!("saved music directory");
     Ok(())
 }
 
+fn relocate(old_prefix: &str, new_prefix: &str, pool: &DBPool) -> Result<(), anyhow::Error> {
+    info!("Relocating {} to {}", old_prefix, new_prefix);
+    let count = db::relocate(old_prefix, new_prefix, pool).map_err(anyhow::Error::msg)?;
+    println!("Relocated {} tracks", count);
+
+    let music_dir = utils::load_preferences().get("music_dir").cloned();
+    if let Some(new_music_dir) = music_dir
+        .as_deref()
+        .and_then(|d| db::relocate_path(d, old_prefix, new_prefix))
+    {
+        utils::save_preference("music_dir", new_music_dir.clone())
+            .map_err(anyhow::Error::msg)
+            .context("Error in saving preferences")?;
+        println!("Music directory is now {}", new_music_dir);
+    } else {
+        println!("Music directory does not start with the old prefix, please set it with -m");
+    }
+    Ok(())
+}
+
+fn render_mix(mix: viola_common::MixJson, pool: &DBPool) -> Result<(), anyhow::Error> {
+    gstreamer::init().context("Could not init gstreamer")?;
+    let tabs = playlist_tabs::load(pool).context("Could not load playlists")?;
+    let replaygain = utils::load_preferences()
+        .get("replaygain")
+        .and_then(|r| serde_json::from_str::<viola_common::ReplayGainJson>(r).ok())
+        .unwrap_or_default();
+    let path = mix::render_tab(&tabs, &mix, replaygain, &|msg| println!("{}", msg))
+        .map_err(anyhow::Error::msg)?;
+    println!("Mix written to {}", path.display());
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
@@ -99,10 +161,32 @@ fn main() -> Result<()> {
         bail!("See Above: ");
     }
     let pool = Arc::new(Mutex::new(tmp_pool.unwrap()));
-    if args.update {
+    if let Some(Command::Relocate {
+        old_prefix,
+        new_prefix,
+    }) = args.command
+    {
+        relocate(&old_prefix, &new_prefix, &pool)?;
+    } else if let Some(Command::Mix {
+        tab,
+        path,
+        format,
+        crossfade,
+    }) = args.command
+    {
+        render_mix(
+            viola_common::MixJson {
+                tab,
+                path,
+                format,
+                crossfade,
+            },
+            &pool,
+        )?;
+    } else if args.update {
         update_db(&pool)?;
     } else if let Some(path) = args.fast_update {
-        update_db_fast(path, &pool);
+        update_db_fast(path, &pool)?;
     } else if let Some(new_music_dir) = args.music_dir {
         set_music_directory(new_music_dir)?;
     } else if args.config_path {
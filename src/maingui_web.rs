@@ -1,21 +1,32 @@
 use futures::StreamExt;
-use log::info;
+use log::{error, info, warn};
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 use std::{io::Read, sync::Arc};
 use tokio::sync::RwLock;
 use viola_common::*;
 use warp::Filter;
 
+use crate::db;
 use crate::gstreamer_wrapper::{self};
 use crate::libraryviewstore;
+use crate::live_stream::{self, IcyInserter};
 use crate::loaded_playlist::SavePlaylistExt;
+use crate::mix;
 use crate::my_websocket;
 use crate::playlist_tabs::{LoadedPlaylistExtImut, PlaylistControlsImut, PlaylistTabsExt};
 use crate::smartplaylist_parser;
+use crate::stations;
+use crate::track_server;
 use crate::types::*;
 
+/// how many upcoming tracks the validator checks
+const VALIDATE_LOOKAHEAD: usize = 20;
+/// how often the validator checks the upcoming tracks
+const VALIDATE_INTERVAL: Duration = Duration::from_secs(30);
+
 /// Handler: returns the current playlist tab items in json
 async fn playlist(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
     let items_json = state.playlist_tabs.items_json();
@@ -36,6 +47,540 @@ async fn repeat(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
     Ok(warp::reply())
 }
 
+/// Handler: returns the repeat mode
+async fn get_repeat_mode(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_repeat()))
+}
+
+/// Handler: sets the repeat mode
+async fn repeat_mode(
+    mode: viola_common::RepeatMode,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    state
+        .gstreamer
+        .do_gstreamer_action(viola_common::GStreamerAction::SetRepeat(mode));
+    Ok(warp::reply())
+}
+
+/// Handler: returns the play mode
+async fn get_play_mode(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_play_mode()))
+}
+
+/// Handler: sets the play mode
+async fn play_mode(
+    mode: viola_common::PlayMode,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    state
+        .gstreamer
+        .do_gstreamer_action(viola_common::GStreamerAction::SetPlayMode(mode));
+    Ok(warp::reply())
+}
+
+/// Handler: returns if we skip tracks we cannot play
+async fn get_skip_errors(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_skip_errors()))
+}
+
+/// Handler: sets if we skip tracks we cannot play or stop playing
+async fn skip_errors(skip: bool, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    state.gstreamer.set_skip_errors(skip);
+    Ok(warp::reply())
+}
+
+/// Handler: returns if we pass over missing files
+async fn get_skip_missing(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_skip_missing()))
+}
+
+/// Handler: sets if we pass over missing files when we go to the next track
+async fn skip_missing(skip: bool, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    state.gstreamer.set_skip_missing(skip);
+    Ok(warp::reply())
+}
+
+/// checks the files of the tracks at `positions` in tab `index` and reloads the playlist in the gui if we found something new
+async fn validate_tracks(state: WebGuiData, index: usize, positions: Vec<usize>) -> Vec<usize> {
+    let tabs = state.playlist_tabs.clone();
+    let Ok((missing, changed)) =
+        tokio::task::spawn_blocking(move || tabs.validate(index, &positions)).await
+    else {
+        error!("Validation task panicked");
+        return Vec::new();
+    };
+    if changed {
+        info!("Missing tracks in tab {} changed: {:?}", index, missing);
+        my_websocket::send_my_message(&state.ws, WsMessage::ReloadPlaylist).await;
+    }
+    missing
+}
+
+/// Handler: checks the files of all tracks in the tab and returns the positions of the missing ones
+async fn validate_tab(index: usize, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    let Some(count) = state
+        .playlist_tabs
+        .read()
+        .pls
+        .get(index)
+        .map(|pl| pl.items.len())
+    else {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&format!("No tab with index {}", index)),
+            warp::http::StatusCode::NOT_FOUND,
+        ));
+    };
+    let missing = validate_tracks(state, index, (0..count).collect()).await;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&missing),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+/// Handler: returns if we load the last track paused on startup
+async fn get_resume_paused(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_resume_paused()))
+}
+
+/// Handler: sets if we load the last track paused at its position on startup
+async fn resume_paused(resume: bool, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    state.gstreamer.set_resume_paused(resume);
+    Ok(warp::reply())
+}
+
+/// Handler: returns the crossfade duration in seconds
+async fn get_crossfade(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_crossfade()))
+}
+
+/// Handler: sets the crossfade duration in seconds, zero disables crossfading
+async fn crossfade(secs: u64, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    info!("Setting crossfade to {}", secs);
+    state.gstreamer.set_crossfade(secs);
+    Ok(warp::reply())
+}
+
+/// Handler: returns the volume in percent and if we are muted
+async fn get_volume(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_volume()))
+}
+
+/// Handler: sets the volume in percent and muting, the change is broadcasted over the websocket
+async fn volume(
+    volume: viola_common::VolumeJson,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("Setting volume to {:?}", volume);
+    state
+        .gstreamer
+        .do_gstreamer_action(viola_common::GStreamerAction::SetVolume(volume.volume));
+    state
+        .gstreamer
+        .do_gstreamer_action(viola_common::GStreamerAction::Mute(volume.muted));
+    Ok(warp::reply())
+}
+
+/// Handler: returns the equalizer gains and presets
+async fn get_equalizer(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_equalizer()))
+}
+
+/// Handler: sets the equalizer gains in dB
+async fn equalizer_gains(
+    gains: [f64; crate::equalizer::BANDS],
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    state.gstreamer.set_equalizer_gains(gains);
+    Ok(warp::reply())
+}
+
+/// Handler: activates the equalizer preset with the given name
+async fn equalizer_preset(name: String, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match state.gstreamer.set_equalizer_preset(&name) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            info!("Could not set preset: {}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::NOT_FOUND,
+            ))
+        }
+    }
+}
+
+/// Handler: saves the current equalizer gains as a preset with the given name
+async fn equalizer_save(name: String, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match state.gstreamer.save_equalizer_preset(name) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            info!("Could not save preset: {}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+/// Handler: deletes the saved equalizer preset with the given name
+async fn delete_equalizer_preset(
+    name: String,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    match state.gstreamer.delete_equalizer_preset(&name) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            info!("Could not delete preset: {}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+/// Handler: returns the ReplayGain settings
+async fn get_replaygain(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_replaygain()))
+}
+
+/// Handler: sets the ReplayGain settings, they apply to the playing track immediately
+async fn replaygain(
+    replaygain: viola_common::ReplayGainJson,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("Setting replaygain to {:?}", replaygain);
+    state.gstreamer.set_replaygain(replaygain);
+    Ok(warp::reply())
+}
+
+/// Handler: lists the audio output devices
+async fn output_devices(_: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match crate::output::list_devices() {
+        Ok(devices) => Ok(warp::reply::with_status(
+            warp::reply::json(&devices),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            error!("Could not list output devices: {}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Handler: returns the output we play to
+async fn get_output(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_output()))
+}
+
+/// Handler: switches the output we play to
+async fn output(
+    output: viola_common::AudioOutput,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("Switching output to {:?}", output);
+    match state.gstreamer.set_output(output) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            info!("Could not switch output: {}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+/// Handler: returns the playback rate
+async fn get_rate(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_rate()))
+}
+
+/// Handler: sets the playback rate, the pitch stays the same
+async fn rate(rate: f64, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    info!("Setting rate to {}", rate);
+    state.gstreamer.set_rate(rate);
+    Ok(warp::reply())
+}
+
+/// Handler: returns the milliseconds between the visualization data, null if it is off
+async fn get_visualization(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_visualization()))
+}
+
+/// Handler: subscribes to the visualization data with the milliseconds between them, null unsubscribes
+async fn visualization(
+    interval: Option<u64>,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("Setting visualization interval to {:?}", interval);
+    state.gstreamer.set_visualization(interval);
+    my_websocket::send_my_message(
+        &state.ws,
+        WsMessage::VisualizationInterval(state.gstreamer.get_visualization()),
+    )
+    .await;
+    Ok(warp::reply())
+}
+
+/// Handler: returns the running sleep timer
+async fn get_sleep_timer(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    Ok(warp::reply::json(&state.gstreamer.get_sleep_timer()))
+}
+
+/// Handler: starts the sleep timer, replacing a running one
+async fn sleep_timer(
+    timer: viola_common::SleepTimer,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    info!("Starting sleep timer {:?}", timer);
+    match state.gstreamer.set_sleep_timer(timer) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            info!("Could not start sleep timer: {}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+/// Handler: cancels the sleep timer
+async fn cancel_sleep_timer(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    info!("Cancelling sleep timer");
+    state.gstreamer.cancel_sleep_timer();
+    Ok(warp::reply())
+}
+
+/// Handler: returns the remembered positions in tracks
+async fn get_bookmarks(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match crate::bookmarks::list(&state.pool) {
+        Ok(bookmarks) => Ok(warp::reply::with_status(
+            warp::reply::json(&bookmarks),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            error!("{}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Handler: forgets all remembered positions
+async fn clear_bookmarks(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match crate::bookmarks::clear(&state.pool) {
+        Ok(count) => {
+            info!("Cleared {} bookmarks", count);
+            Ok(warp::reply::with_status(
+                String::new(),
+                warp::http::StatusCode::OK,
+            ))
+        }
+        Err(e) => {
+            error!("{}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Handler: forgets the remembered position of one track
+async fn clear_bookmark(id: i32, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match crate::bookmarks::remove(&state.pool, id) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            error!("{}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Handler: returns all internet radio stations
+async fn get_stations(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match stations::list(&state.pool) {
+        Ok(list) => Ok(warp::reply::with_status(
+            warp::reply::json(&list),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            error!("{}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e),
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Handler: adds an internet radio station and returns it
+async fn add_station(
+    station: NewStationJson,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    match stations::add(&state.pool, &station) {
+        Ok(station) => {
+            info!("Added station {} with {}", station.name, station.url);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&station),
+                warp::http::StatusCode::OK,
+            ))
+        }
+        Err(e) => {
+            warn!("{}", e);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&e),
+                warp::http::StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+/// Handler: appends the station to the current playlist tab
+async fn station_to_tab(id: i32, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    let Some(station) = stations::get(&state.pool, id) else {
+        return Ok(warp::reply::with_status(
+            format!("No station with id {}", id),
+            warp::http::StatusCode::NOT_FOUND,
+        ));
+    };
+    info!("Adding station {} to the current tab", station.name);
+    state.playlist_tabs.append(Track::from(station));
+    tokio::spawn(async move {
+        my_websocket::send_my_message(&state.ws, WsMessage::ReloadPlaylist).await;
+    });
+    Ok(warp::reply::with_status(
+        String::new(),
+        warp::http::StatusCode::OK,
+    ))
+}
+
+/// Handler: removes the station, tabs that are loaded keep it until we restart
+async fn delete_station(id: i32, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    match stations::remove(&state.pool, id) {
+        Ok(()) => Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::OK,
+        )),
+        Err(e) => {
+            error!("{}", e);
+            Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+            ))
+        }
+    }
+}
+
+/// Handler: streams what we play, with ICY metadata every `live_stream::ICY_METAINT` bytes if the client asks for it
+async fn stream(
+    icy_metadata: Option<String>,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    let Some(live) = state.gstreamer.live_stream() else {
+        return Ok(warp::http::Response::builder()
+            .status(warp::http::StatusCode::NOT_FOUND)
+            .body(warp::hyper::Body::from("The live stream is off"))
+            .unwrap());
+    };
+    info!("New listener for the live stream");
+    let content_type = live.content_type();
+    let listener = live.subscribe();
+    let mut icy = (icy_metadata.as_deref() == Some("1")).then(IcyInserter::new);
+    let mut response = warp::http::Response::builder()
+        .header("Content-Type", content_type)
+        .header("Cache-Control", "no-cache")
+        .header("icy-name", "viola");
+    if icy.is_some() {
+        response = response.header("icy-metaint", live_stream::ICY_METAINT);
+    }
+    let gstreamer = state.gstreamer.clone();
+    let body = futures::stream::unfold(listener, |mut listener| async move {
+        let data = listener.next().await?;
+        Some((data, listener))
+    })
+    .map(move |data| {
+        Ok::<_, Infallible>(match icy.as_mut() {
+            Some(icy) => icy.insert(&data, &gstreamer.now_playing_title()),
+            None => data,
+        })
+    });
+    Ok(response.body(warp::hyper::Body::wrap_stream(body)).unwrap())
+}
+
+/// Handler: serves the file of the library track with `id`, transcoded if the query asks for a format
+async fn track_file(
+    id: i32,
+    query: track_server::TrackQuery,
+    range: Option<String>,
+    state: WebGuiData,
+) -> Result<impl warp::Reply, Infallible> {
+    let Some(track) = track_server::get(&state.pool, id) else {
+        return Ok(warp::http::Response::builder()
+            .status(warp::http::StatusCode::NOT_FOUND)
+            .body(warp::hyper::Body::from(format!("No track with id {}", id)))
+            .unwrap());
+    };
+    let path = std::path::PathBuf::from(&track.path);
+    match query.format.map(|f| f.parse::<MixFormat>()) {
+        None => Ok(track_server::serve_file(&path, range).await),
+        Some(Ok(format)) => Ok(track_server::serve_transcoded(path, format)),
+        Some(Err(e)) => Ok(warp::http::Response::builder()
+            .status(warp::http::StatusCode::BAD_REQUEST)
+            .body(warp::hyper::Body::from(e))
+            .unwrap()),
+    }
+}
+
+/// Handler: counts the library track with `id` as played, for tracks a browser played to the end
+async fn track_played(id: i32, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    use crate::db::UpdatePlayCount;
+
+    let Some(mut track) = track_server::get(&state.pool, id) else {
+        return Ok(warp::reply::with_status(
+            format!("No track with id {}", id),
+            warp::http::StatusCode::NOT_FOUND,
+        ));
+    };
+    state.playlist_tabs.update_playcount(id);
+    let pool = state.pool.clone();
+    tokio::task::spawn_blocking(move || track.update_playcount(pool));
+    Ok(warp::reply::with_status(
+        String::new(),
+        warp::http::StatusCode::OK,
+    ))
+}
+
 /// Handler: removes all already played data
 async fn clean(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
     info!("doing cleaning");
@@ -316,11 +861,141 @@ async fn delete_playlist_tab(
     Ok(warp::reply())
 }
 
+/// rescans the music dir, reporting the progress to `progress`
+fn scan_library(pool: &DBPool, progress: &(dyn Fn(ScanMessage) + Sync)) -> Result<(), String> {
+    let music_dir = crate::utils::load_preferences()
+        .get("music_dir")
+        .cloned()
+        .ok_or_else(|| String::from("Could not get musicdir"))?;
+    db::build_db(&music_dir, pool, true, progress)?;
+    smartplaylist_parser::m3u_from_smartplaylist(&music_dir, pool).map_err(|e| e.to_string())
+}
+
+/// Handler: starts a library scan in the background, rejects it if one is already running
+async fn scan(state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    if state.scanning.swap(true, Ordering::SeqCst) {
+        info!("Scan already running");
+        return Ok(warp::reply::with_status(
+            warp::reply(),
+            warp::http::StatusCode::CONFLICT,
+        ));
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                my_websocket::send_my_message(&state.ws, WsMessage::Scan(msg)).await;
+            }
+        });
+    }
+    tokio::spawn(async move {
+        let pool = state.pool.clone();
+        let done = tx.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let progress = |msg| {
+                if tx.send(msg).is_err() {
+                    info!("Could not send scan progress");
+                }
+            };
+            progress(ScanMessage::Started);
+            if let Err(err) = scan_library(&pool, &progress) {
+                progress(ScanMessage::Error(err));
+            }
+            progress(ScanMessage::Finished);
+        })
+        .await;
+        if res.is_err() {
+            error!("Scan task panicked");
+            let _ = done.send(ScanMessage::Error(String::from(
+                "The scan stopped unexpectedly",
+            )));
+            let _ = done.send(ScanMessage::Finished);
+        }
+        state.scanning.store(false, Ordering::SeqCst);
+    });
+    Ok(warp::reply::with_status(
+        warp::reply(),
+        warp::http::StatusCode::ACCEPTED,
+    ))
+}
+
+/// Handler: renders a tab into one file below the `mix_dir` preference in the background, rejects it if a mix is already rendering
+async fn mix(mix: MixJson, state: WebGuiData) -> Result<impl warp::Reply, Infallible> {
+    let path = crate::utils::load_preferences()
+        .get("mix_dir")
+        .ok_or_else(|| String::from("Set mix_dir in the preferences to render mixes"))
+        .and_then(|dir| mix::path_in(std::path::Path::new(dir), &mix.path));
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => {
+            info!("Not rendering mix: {}", e);
+            return Ok(warp::reply::with_status(
+                e,
+                warp::http::StatusCode::BAD_REQUEST,
+            ));
+        }
+    };
+    let mix = MixJson {
+        path: path.to_string_lossy().into_owned(),
+        ..mix
+    };
+    if state.mixing.swap(true, Ordering::SeqCst) {
+        info!("Mix already rendering");
+        return Ok(warp::reply::with_status(
+            String::new(),
+            warp::http::StatusCode::CONFLICT,
+        ));
+    }
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                my_websocket::send_my_message(&state.ws, WsMessage::Mix(msg)).await;
+            }
+        });
+    }
+    tokio::spawn(async move {
+        let tabs = state.playlist_tabs.clone();
+        let replaygain = state.gstreamer.get_replaygain();
+        let res = tokio::task::spawn_blocking(move || {
+            let progress = |msg| {
+                if tx.send(msg).is_err() {
+                    info!("Could not send mix progress");
+                }
+            };
+            match mix::render_tab(&tabs, &mix, replaygain, &progress) {
+                Ok(path) => progress(MixMessage::Finished(path.to_string_lossy().into_owned())),
+                Err(err) => {
+                    warn!("Could not render mix: {}", err);
+                    progress(MixMessage::Error(err));
+                }
+            }
+        })
+        .await;
+        if res.is_err() {
+            error!("Mix task panicked");
+        }
+        state.mixing.store(false, Ordering::SeqCst);
+    });
+    Ok(warp::reply::with_status(
+        String::new(),
+        warp::http::StatusCode::ACCEPTED,
+    ))
+}
+
 struct WebGui {
     pool: DBPool,
     gstreamer: Arc<gstreamer_wrapper::GStreamer>,
     playlist_tabs: PlaylistTabsPtr,
     ws: my_websocket::MyWs,
+    /// is a library scan running
+    scanning: AtomicBool,
+    /// is a mix rendering
+    mixing: AtomicBool,
 }
 
 impl WebGui {
@@ -361,7 +1036,12 @@ async fn handle_gstreamer_messages(
             GStreamerMessage::Pausing
             | GStreamerMessage::Stopped
             | GStreamerMessage::IncreasePlayCount(_)
-            | GStreamerMessage::FileNotFound => {
+            | GStreamerMessage::FileNotFound
+            | GStreamerMessage::VolumeChanged(_)
+            | GStreamerMessage::RateChanged
+            | GStreamerMessage::RepeatChanged(_)
+            | GStreamerMessage::PlayModeChanged(_)
+            | GStreamerMessage::Seeked(_) => {
                 tokio::spawn(async move {
                     //let state = state.clone();
                     my_websocket::send_my_message(
@@ -371,6 +1051,21 @@ async fn handle_gstreamer_messages(
                     .await;
                 });
             }
+            GStreamerMessage::PlaybackError => {
+                let errors = state.gstreamer.take_errors();
+                tokio::spawn(async move {
+                    for error in errors {
+                        my_websocket::send_my_message(&state.ws, WsMessage::PlaybackError(error))
+                            .await;
+                    }
+                });
+            }
+            GStreamerMessage::StreamTitleChanged => {
+                let title = state.gstreamer.get_stream_title();
+                tokio::spawn(async move {
+                    my_websocket::send_my_message(&state.ws, WsMessage::StreamTitle(title)).await;
+                });
+            }
             GStreamerMessage::Nop | GStreamerMessage::ChangedDuration(_) => {}
         }
     }
@@ -407,6 +1102,8 @@ pub async fn run(pool: DBPool) {
         gstreamer: gst,
         playlist_tabs: plt,
         ws: Arc::new(RwLock::new(None)),
+        scanning: AtomicBool::new(false),
+        mixing: AtomicBool::new(false),
     };
 
     info!("Doing data");
@@ -441,10 +1138,60 @@ pub async fn run(pool: DBPool) {
             }
         });
     }
+    {
+        let datac = state.clone();
+        tokio::spawn(async move {
+            // we look for missing files before playback reaches them
+            loop {
+                tokio::time::sleep(VALIDATE_INTERVAL).await;
+                if matches!(
+                    datac.gstreamer.get_state(),
+                    GStreamerMessage::Playing | GStreamerMessage::Pausing
+                ) {
+                    let positions = datac.gstreamer.upcoming_positions(VALIDATE_LOOKAHEAD);
+                    let tab = datac.playlist_tabs.current_tab();
+                    validate_tracks(datac.clone(), tab, positions).await;
+                }
+            }
+        });
+    }
+    {
+        let datac = state.clone();
+        tokio::spawn(async move {
+            // we send the sleep timer every second while it runs and once more when it ended
+            let mut running = false;
+            loop {
+                tokio::time::sleep(Duration::new(1, 0)).await;
+                let timer = datac.gstreamer.check_sleep_timer();
+                if running || timer.is_some() {
+                    my_websocket::send_my_message(&datac.ws, WsMessage::SleepTimer(timer)).await;
+                }
+                running = timer.is_some();
+            }
+        });
+    }
+
+    {
+        let datac = state.clone();
+        let mut visualization = datac.gstreamer.subscribe_visualization();
+        tokio::spawn(async move {
+            // the data only changes while a client subscribed to it
+            while visualization.changed().await.is_ok() {
+                if datac.ws.read().await.is_none() {
+                    info!("Websocket is gone, turning the visualization off");
+                    datac.gstreamer.set_visualization(None);
+                    continue;
+                }
+                let data = visualization.borrow_and_update().clone();
+                my_websocket::send_my_message(&datac.ws, WsMessage::Visualization(data)).await;
+            }
+        });
+    }
 
     println!("Starting web gui on {}", crate::types::URL);
 
     let statec = state.clone();
+    let shutdown_state = state.clone();
     let data = warp::any().map(move || Arc::clone(&state));
 
     let gets = {
@@ -477,6 +1224,57 @@ pub async fn run(pool: DBPool) {
             .and(data.clone())
             .and_then(smartplaylist)
             .with(warp::compression::brotli());
+        let crossf = warp::path!("crossfade")
+            .and(data.clone())
+            .and_then(get_crossfade);
+        let vol = warp::path!("volume").and(data.clone()).and_then(get_volume);
+        let eq = warp::path!("equalizer")
+            .and(data.clone())
+            .and_then(get_equalizer);
+        let rg = warp::path!("replaygain")
+            .and(data.clone())
+            .and_then(get_replaygain);
+        let outputs = warp::path!("outputs")
+            .and(data.clone())
+            .and_then(output_devices);
+        let outp = warp::path!("output").and(data.clone()).and_then(get_output);
+        let rt = warp::path!("rate").and(data.clone()).and_then(get_rate);
+        let vis = warp::path!("visualization")
+            .and(data.clone())
+            .and_then(get_visualization);
+        let live = warp::path!("stream")
+            .and(warp::header::optional::<String>("icy-metadata"))
+            .and(data.clone())
+            .and_then(stream);
+        let track = warp::path!("track" / i32)
+            .and(warp::query::<track_server::TrackQuery>())
+            .and(warp::header::optional::<String>("range"))
+            .and(data.clone())
+            .and_then(track_file);
+        let sleep = warp::path!("sleeptimer")
+            .and(data.clone())
+            .and_then(get_sleep_timer);
+        let rep_mode = warp::path!("repeatmode")
+            .and(data.clone())
+            .and_then(get_repeat_mode);
+        let pl_mode = warp::path!("playmode")
+            .and(data.clone())
+            .and_then(get_play_mode);
+        let skip = warp::path!("skiperrors")
+            .and(data.clone())
+            .and_then(get_skip_errors);
+        let skip_miss = warp::path!("skipmissing")
+            .and(data.clone())
+            .and_then(get_skip_missing);
+        let bookm = warp::path!("bookmarks")
+            .and(data.clone())
+            .and_then(get_bookmarks);
+        let resume = warp::path!("resumepaused")
+            .and(data.clone())
+            .and_then(get_resume_paused);
+        let stat = warp::path!("stations")
+            .and(data.clone())
+            .and_then(get_stations);
         warp::get().and(
             pl.or(pl_for)
                 .or(tr)
@@ -484,7 +1282,25 @@ pub async fn run(pool: DBPool) {
                 .or(curfancy)
                 .or(pltab)
                 .or(cover)
-                .or(smartpl),
+                .or(smartpl)
+                .or(crossf)
+                .or(vol)
+                .or(eq)
+                .or(rg)
+                .or(outputs)
+                .or(outp)
+                .or(rt)
+                .or(vis)
+                .or(live)
+                .or(track)
+                .or(sleep)
+                .or(rep_mode)
+                .or(pl_mode)
+                .or(skip)
+                .or(skip_miss)
+                .or(resume)
+                .or(bookm)
+                .or(stat),
         )
     };
 
@@ -492,6 +1308,84 @@ pub async fn run(pool: DBPool) {
         let rep = warp::path!("repeat").and(data.clone()).and_then(repeat);
         let clean = warp::path!("clean").and(data.clone()).and_then(clean);
         let save = warp::path!("save").and(data.clone()).and_then(save);
+        let scan = warp::path!("scan").and(data.clone()).and_then(scan);
+        let mix = warp::path!("mix")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(mix);
+        let crossf = warp::path!("crossfade")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(crossfade);
+        let vol = warp::path!("volume")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(volume);
+        let eq_gains = warp::path!("equalizer" / "gains")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(equalizer_gains);
+        let eq_preset = warp::path!("equalizer" / "preset")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(equalizer_preset);
+        let eq_save = warp::path!("equalizer" / "save")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(equalizer_save);
+        let rg = warp::path!("replaygain")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(replaygain);
+        let outp = warp::path!("output")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(output);
+        let rt = warp::path!("rate")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(rate);
+        let vis = warp::path!("visualization")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(visualization);
+        let sleep = warp::path!("sleeptimer")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(sleep_timer);
+        let rep_mode = warp::path!("repeatmode")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(repeat_mode);
+        let pl_mode = warp::path!("playmode")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(play_mode);
+        let skip = warp::path!("skiperrors")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(skip_errors);
+        let skip_miss = warp::path!("skipmissing")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(skip_missing);
+        let validate = warp::path!("validate" / usize)
+            .and(data.clone())
+            .and_then(validate_tab);
+        let resume = warp::path!("resumepaused")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(resume_paused);
+        let stat = warp::path!("stations")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(add_station);
+        let stat_tab = warp::path!("stations" / i32)
+            .and(data.clone())
+            .and_then(station_to_tab);
+        let played = warp::path!("track" / i32 / "played")
+            .and(data.clone())
+            .and_then(track_played);
         let transp = warp::path!("transport")
             .and(warp::body::json())
             .and(data.clone())
@@ -520,6 +1414,27 @@ pub async fn run(pool: DBPool) {
         warp::post().and(
             rep.or(clean)
                 .or(save)
+                .or(scan)
+                .or(mix)
+                .or(crossf)
+                .or(vol)
+                .or(eq_gains)
+                .or(eq_preset)
+                .or(eq_save)
+                .or(rg)
+                .or(outp)
+                .or(rt)
+                .or(vis)
+                .or(sleep)
+                .or(rep_mode)
+                .or(pl_mode)
+                .or(skip)
+                .or(skip_miss)
+                .or(validate)
+                .or(resume)
+                .or(stat)
+                .or(stat_tab)
+                .or(played)
                 .or(transp)
                 .or(play)
                 .or(playlist_tab)
@@ -537,7 +1452,31 @@ pub async fn run(pool: DBPool) {
         let deletetab = warp::path!("playlisttab" / usize)
             .and(data.clone())
             .and_then(delete_playlist_tab);
-        warp::delete().and(deletepl.or(deletetab))
+        let deletesleep = warp::path!("sleeptimer")
+            .and(data.clone())
+            .and_then(cancel_sleep_timer);
+        let deletebookmarks = warp::path!("bookmarks")
+            .and(data.clone())
+            .and_then(clear_bookmarks);
+        let deletebookmark = warp::path!("bookmarks" / i32)
+            .and(data.clone())
+            .and_then(clear_bookmark);
+        let deletestation = warp::path!("stations" / i32)
+            .and(data.clone())
+            .and_then(delete_station);
+        let deletepreset = warp::path!("equalizer" / "preset")
+            .and(warp::body::json())
+            .and(data.clone())
+            .and_then(delete_equalizer_preset);
+        warp::delete().and(
+            deletepl
+                .or(deletetab)
+                .or(deletesleep)
+                .or(deletebookmarks)
+                .or(deletebookmark)
+                .or(deletestation)
+                .or(deletepreset),
+        )
     };
 
     let puts = {
@@ -556,6 +1495,12 @@ pub async fn run(pool: DBPool) {
                 info!("doing new websocket connection");
                 let (tx, _) = websocket.split();
                 *statec.ws.write().await = Some(tx);
+                // the client shows the meter if the visualization is on
+                my_websocket::send_my_message(
+                    &statec.ws,
+                    WsMessage::VisualizationInterval(statec.gstreamer.get_visualization()),
+                )
+                .await;
             })
         });
     //let web_gui_path = concat!(env!("CARGO_MANIFEST_DIR"), "/web_gui_seed/dist/index.html");
@@ -574,5 +1519,16 @@ pub async fn run(pool: DBPool) {
         .or(websocket)
         .or(index);
     let s: SocketAddr = crate::types::SOCKETADDR.parse().unwrap();
-    warp::serve(all).run(s).await;
+    let (_, server) = warp::serve(all).bind_with_graceful_shutdown(s, async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Could not listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    });
+    server.await;
+
+    info!("Shutting down");
+    shutdown_state.gstreamer.save_bookmark();
+    shutdown_state.gstreamer.save_resume_position();
+    shutdown_state.save();
 }
@@ -0,0 +1,560 @@
+use gstreamer::prelude::*;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::path::{Component, Path, PathBuf};
+use viola_common::{MixFormat, MixJson, MixMessage, ReplayGainJson, ReplayGainMode, Track};
+
+use crate::types::PlaylistTabsPtr;
+
+/// sample rate of the mix before encoding
+const RATE: u64 = 44100;
+/// channels of the mix, samples are interleaved
+const CHANNELS: usize = 2;
+/// the format the decoders convert to and the encoder reads
+const CAPS: &str = "audio/x-raw,format=F32LE,rate=44100,channels=2,layout=interleaved";
+/// how long we wait for a decoded sample before we check the decoder for errors
+const PULL_TIMEOUT: gstreamer::ClockTime = gstreamer::ClockTime::from_mseconds(100);
+/// frames per second of the positions in a CUE sheet
+const CUE_FRAMES: u64 = 75;
+/// longest crossfade in seconds we render
+pub(crate) const MAX_CROSSFADE: u64 = 30;
+
+/// the elements encoding raw audio in `format`
+pub(crate) fn encoder_description(format: MixFormat) -> &'static str {
+    match format {
+        MixFormat::Flac => "flacenc",
+        MixFormat::Opus => "opusenc bitrate=192000 ! oggmux",
+        MixFormat::Mp3 => "lamemp3enc target=bitrate bitrate=320 cbr=true",
+    }
+}
+
+/// the first error on the bus of `pipeline`
+fn pipeline_error(pipeline: &gstreamer::Pipeline) -> Option<String> {
+    let bus = pipeline.bus()?;
+    bus.pop_filtered(&[gstreamer::MessageType::Error])
+        .and_then(|msg| match msg.view() {
+            gstreamer::MessageView::Error(err) => Some(err.error().to_string()),
+            _ => None,
+        })
+}
+
+/// builds the pipeline described by `description`
+pub(crate) fn make_pipeline(description: &str) -> Result<gstreamer::Pipeline, String> {
+    gstreamer::parse::launch(description)
+        .map_err(|e| format!("Cannot do gstreamer: {}", e))?
+        .downcast::<gstreamer::Pipeline>()
+        .map_err(|_| String::from("Not a pipeline"))
+}
+
+/// the element called `name` in `pipeline`
+pub(crate) fn element(
+    pipeline: &gstreamer::Pipeline,
+    name: &str,
+) -> Result<gstreamer::Element, String> {
+    pipeline
+        .by_name(name)
+        .ok_or_else(|| format!("Could not find {}", name))
+}
+
+/// writes a short tone as a wav file to `path`, whatever its extension is
+#[cfg(test)]
+pub(crate) fn write_tone(path: &Path) {
+    let pipeline =
+        make_pipeline("audiotestsrc num-buffers=10 ! wavenc ! filesink name=sink").unwrap();
+    element(&pipeline, "sink")
+        .unwrap()
+        .set_property("location", &*path.to_string_lossy());
+    pipeline.set_state(gstreamer::State::Playing).unwrap();
+    pipeline.bus().unwrap().timed_pop_filtered(
+        gstreamer::ClockTime::from_seconds(10),
+        &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+    );
+    pipeline.set_state(gstreamer::State::Null).unwrap();
+}
+
+fn frames_to_time(frames: u64) -> gstreamer::ClockTime {
+    gstreamer::ClockTime::from_nseconds(frames * 1_000_000_000 / RATE)
+}
+
+/// Encodes the samples we push into a file
+struct Encoder {
+    pipeline: gstreamer::Pipeline,
+    src: gstreamer::Element,
+    /// frames we pushed so far
+    frames: u64,
+}
+
+impl Encoder {
+    fn new(path: &Path, format: MixFormat) -> Result<Self, String> {
+        let pipeline = make_pipeline(&format!(
+            "appsrc name=src format=time block=true ! audioconvert ! audioresample ! {} ! filesink name=sink",
+            encoder_description(format)
+        ))?;
+        let src = element(&pipeline, "src")?;
+        let caps = CAPS
+            .parse::<gstreamer::Caps>()
+            .map_err(|e| format!("Invalid caps: {}", e))?;
+        src.set_property("caps", &caps);
+        element(&pipeline, "sink")?.set_property("location", path.to_string_lossy().as_ref());
+        pipeline
+            .set_state(gstreamer::State::Playing)
+            .map_err(|e| format!("Could not start encoder: {}", e))?;
+        Ok(Encoder {
+            pipeline,
+            src,
+            frames: 0,
+        })
+    }
+
+    fn push(&mut self, samples: &[f32]) -> Result<(), String> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let frames = (samples.len() / CHANNELS) as u64;
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut buffer = gstreamer::Buffer::from_mut_slice(data);
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(frames_to_time(self.frames));
+            buffer.set_duration(frames_to_time(frames));
+        }
+        self.frames += frames;
+        match self
+            .src
+            .emit_by_name::<gstreamer::FlowReturn>("push-buffer", &[&buffer])
+        {
+            gstreamer::FlowReturn::Ok => Ok(()),
+            ret => Err(pipeline_error(&self.pipeline)
+                .unwrap_or_else(|| format!("Encoder stopped with {:?}", ret))),
+        }
+    }
+
+    /// writes the rest of the file, dropping stops the encoder
+    fn finish(self) -> Result<(), String> {
+        self.src
+            .emit_by_name::<gstreamer::FlowReturn>("end-of-stream", &[]);
+        let msg = self.pipeline.bus().and_then(|bus| {
+            bus.timed_pop_filtered(
+                gstreamer::ClockTime::NONE,
+                &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+            )
+        });
+        let msg = msg.ok_or_else(|| String::from("Encoder has no bus"))?;
+        if let gstreamer::MessageView::Error(err) = msg.view() {
+            return Err(err.error().to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gstreamer::State::Null);
+    }
+}
+
+/// Decodes `uri` and calls `f` with the samples, applying ReplayGain if it is not off
+fn decode(
+    uri: &str,
+    replaygain: ReplayGainJson,
+    album_mode: bool,
+    f: &mut dyn FnMut(&[f32]) -> Result<(), String>,
+) -> Result<(), String> {
+    let rg = if replaygain.mode == ReplayGainMode::Off {
+        ""
+    } else {
+        "rgvolume name=rgvolume ! rglimiter ! audioconvert ! "
+    };
+    let pipeline = make_pipeline(&format!(
+        "uridecodebin name=dec ! audioconvert ! {}audioresample ! capsfilter name=caps ! appsink name=sink sync=false",
+        rg
+    ))?;
+    element(&pipeline, "dec")?.set_property("uri", uri);
+    let caps = CAPS
+        .parse::<gstreamer::Caps>()
+        .map_err(|e| format!("Invalid caps: {}", e))?;
+    element(&pipeline, "caps")?.set_property("caps", &caps);
+    if let Some(rgvolume) = pipeline.by_name("rgvolume") {
+        rgvolume.set_property("album-mode", album_mode);
+        rgvolume.set_property("pre-amp", replaygain.preamp);
+        rgvolume.set_property("fallback-gain", replaygain.fallback_gain);
+    }
+    let sink = element(&pipeline, "sink")?;
+    pull(&pipeline, &sink, uri, &mut |data| {
+        let samples: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        f(&samples)
+    })
+}
+
+/// Plays `pipeline` and calls `f` with the buffers of the appsink `sink` until the end of `name`
+pub(crate) fn pull(
+    pipeline: &gstreamer::Pipeline,
+    sink: &gstreamer::Element,
+    name: &str,
+    f: &mut dyn FnMut(&[u8]) -> Result<(), String>,
+) -> Result<(), String> {
+    pipeline
+        .set_state(gstreamer::State::Playing)
+        .map_err(|e| format!("Could not decode {}: {}", name, e))?;
+    let res = loop {
+        let sample = sink.emit_by_name::<Option<gstreamer::Sample>>(
+            "try-pull-sample",
+            &[&PULL_TIMEOUT.nseconds()],
+        );
+        let Some(sample) = sample else {
+            if sink.property::<bool>("eos") {
+                break Ok(());
+            }
+            if let Some(err) = pipeline_error(pipeline) {
+                break Err(format!("Could not decode {}: {}", name, err));
+            }
+            continue;
+        };
+        let Some(buffer) = sample.buffer() else {
+            continue;
+        };
+        let Ok(map) = buffer.map_readable() else {
+            break Err(format!("Could not read the samples of {}", name));
+        };
+        if let Err(e) = f(map.as_slice()) {
+            break Err(e);
+        }
+    };
+    let _ = pipeline.set_state(gstreamer::State::Null);
+    res
+}
+
+/// Joins tracks into one stream of samples, holding back the end of a track so the next one can fade over it
+struct Mixer<W: FnMut(&[f32]) -> Result<(), String>> {
+    out: W,
+    /// length of the crossfade in samples
+    fade: usize,
+    /// the end of the current track we did not write yet
+    pending: VecDeque<f32>,
+    /// the end of the previous track the current one fades over
+    fading: Vec<f32>,
+    /// how many samples of `fading` we already mixed
+    faded: usize,
+    /// frames we wrote
+    written: u64,
+}
+
+impl<W: FnMut(&[f32]) -> Result<(), String>> Mixer<W> {
+    fn new(crossfade_frames: usize, out: W) -> Self {
+        Mixer {
+            out,
+            fade: crossfade_frames * CHANNELS,
+            pending: VecDeque::new(),
+            fading: Vec::new(),
+            faded: 0,
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.written += (samples.len() / CHANNELS) as u64;
+        (self.out)(samples)
+    }
+
+    /// starts the next track, fading it over the previous one if `crossfade`. Returns the frame the track starts at
+    fn start_track(&mut self, crossfade: bool) -> Result<u64, String> {
+        let pending = Vec::from(std::mem::take(&mut self.pending));
+        if crossfade {
+            self.fading = pending;
+            self.faded = 0;
+        } else {
+            self.write(&pending)?;
+        }
+        Ok(self.written)
+    }
+
+    /// adds samples of the current track
+    fn add(&mut self, samples: &[f32]) -> Result<(), String> {
+        let mut rest = samples;
+        if self.faded < self.fading.len() {
+            let n = rest.len().min(self.fading.len() - self.faded);
+            let frames = (self.fading.len() / CHANNELS) as f32;
+            let mixed: Vec<f32> = self.fading[self.faded..self.faded + n]
+                .iter()
+                .zip(&rest[..n])
+                .enumerate()
+                .map(|(i, (old, new))| {
+                    let gain = ((self.faded + i) / CHANNELS) as f32 / frames;
+                    old * (1.0 - gain) + new * gain
+                })
+                .collect();
+            self.faded += n;
+            self.write(&mixed)?;
+            rest = &rest[n..];
+        }
+        self.pending.extend(rest);
+        if self.pending.len() > self.fade {
+            let excess = self.pending.len() - self.fade;
+            let samples: Vec<f32> = self.pending.drain(..excess).collect();
+            self.write(&samples)?;
+        }
+        Ok(())
+    }
+
+    /// ends the current track, the rest of the previous track plays out if the current one was shorter than the fade
+    fn end_track(&mut self) -> Result<(), String> {
+        let fading = std::mem::take(&mut self.fading);
+        self.write(&fading[self.faded.min(fading.len())..])?;
+        self.faded = 0;
+        Ok(())
+    }
+
+    /// writes the end of the last track
+    fn finish(mut self) -> Result<(), String> {
+        let pending = Vec::from(std::mem::take(&mut self.pending));
+        self.write(&pending)
+    }
+}
+
+/// a string we can put in quotes in a CUE sheet
+fn cue_escape(s: &str) -> String {
+    s.replace('"', "'")
+}
+
+/// `frames` as a CUE position, i.e., minutes, seconds and 1/75 seconds
+fn cue_position(frames: u64) -> String {
+    let cue_frames = frames * CUE_FRAMES / RATE;
+    format!(
+        "{:02}:{:02}:{:02}",
+        cue_frames / (CUE_FRAMES * 60),
+        (cue_frames / CUE_FRAMES) % 60,
+        cue_frames % CUE_FRAMES
+    )
+}
+
+/// a CUE sheet for the mix in `file` with the tracks starting at the given frames
+fn cue_sheet(title: &str, file: &str, format: MixFormat, tracks: &[(&Track, u64)]) -> String {
+    let file_type = if format == MixFormat::Mp3 {
+        "MP3"
+    } else {
+        "WAVE"
+    };
+    let mut cue = format!(
+        "TITLE \"{}\"\nFILE \"{}\" {}\n",
+        cue_escape(title),
+        cue_escape(file),
+        file_type
+    );
+    for (i, (track, start)) in tracks.iter().enumerate() {
+        cue.push_str(&format!(
+            "  TRACK {:02} AUDIO\n    TITLE \"{}\"\n    PERFORMER \"{}\"\n    INDEX 01 {}\n",
+            i + 1,
+            cue_escape(&track.title),
+            cue_escape(&track.artist),
+            cue_position(*start)
+        ));
+    }
+    cue
+}
+
+/// Do we use the album gain for the track at `i`, i.e., does a neighbouring track have the same album
+fn album_mode(replaygain: ReplayGainJson, tracks: &[Track], i: usize) -> bool {
+    match replaygain.mode {
+        ReplayGainMode::Album => true,
+        ReplayGainMode::Off | ReplayGainMode::Track => false,
+        ReplayGainMode::Auto => {
+            !tracks[i].album.is_empty()
+                && [i.checked_sub(1), i.checked_add(1)]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|j| tracks.get(j))
+                    .any(|t| t.album == tracks[i].album)
+        }
+    }
+}
+
+/// Renders `tracks` into one file at `path` and writes a CUE sheet with the track boundaries next to it.
+/// Streams and missing files are left out. We do not crossfade between tracks of the same album.
+/// The crossfade is at most `MAX_CROSSFADE` seconds.
+pub(crate) fn render(
+    title: &str,
+    tracks: &[Track],
+    path: &Path,
+    format: MixFormat,
+    crossfade: u64,
+    replaygain: ReplayGainJson,
+    progress: &dyn Fn(MixMessage),
+) -> Result<(), String> {
+    let tracks: Vec<Track> = tracks
+        .iter()
+        .filter(|t| {
+            let available = !t.is_stream() && Path::new(&t.path).exists();
+            if !available {
+                warn!("Leaving {} out of the mix", t.path);
+            }
+            available
+        })
+        .cloned()
+        .collect();
+    if tracks.is_empty() {
+        return Err(String::from("No tracks to mix"));
+    }
+    progress(MixMessage::Started(tracks.len()));
+
+    let crossfade = crossfade.min(MAX_CROSSFADE);
+    let mut encoder = Encoder::new(path, format)?;
+    let mut mixer = Mixer::new(crossfade as usize * RATE as usize, |samples: &[f32]| {
+        encoder.push(samples)
+    });
+    let mut starts = Vec::with_capacity(tracks.len());
+    for (i, track) in tracks.iter().enumerate() {
+        progress(MixMessage::Rendering(i + 1, tracks.len()));
+        info!("Mixing {}", track.path);
+        let fade = crossfade > 0
+            && i > 0
+            && (track.album.is_empty() || track.album != tracks[i - 1].album);
+        starts.push((track, mixer.start_track(fade)?));
+        let uri = gstreamer::glib::filename_to_uri(&track.path, None)
+            .map_err(|e| format!("Invalid path {}: {}", track.path, e))?;
+        decode(
+            &uri,
+            replaygain,
+            album_mode(replaygain, &tracks, i),
+            &mut |samples: &[f32]| mixer.add(samples),
+        )?;
+        mixer.end_track()?;
+    }
+    mixer.finish()?;
+    encoder.finish()?;
+
+    let file = path
+        .file_name()
+        .map(|f| f.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    std::fs::write(
+        path.with_extension("cue"),
+        cue_sheet(title, &file, format, &starts),
+    )
+    .map_err(|e| format!("Could not write CUE sheet: {}", e))
+}
+
+/// the path of a file a client asked for, i.e., a mix, `path` has to be relative and stay inside `dir`
+pub(crate) fn path_in(dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(path);
+    if relative.as_os_str().is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(format!(
+            "{} is not a relative path inside {}",
+            path,
+            dir.display()
+        ));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Renders the tab given in `mix`, see `render`. Returns the path of the mix
+pub(crate) fn render_tab(
+    tabs: &PlaylistTabsPtr,
+    mix: &MixJson,
+    replaygain: ReplayGainJson,
+    progress: &dyn Fn(MixMessage),
+) -> Result<PathBuf, String> {
+    let (name, tracks) = {
+        let tabs = tabs.read();
+        let pl = tabs
+            .pls
+            .get(mix.tab)
+            .ok_or_else(|| format!("No tab with index {}", mix.tab))?;
+        (pl.name.clone(), pl.items.clone())
+    };
+    let mut path = PathBuf::from(&mix.path);
+    if path.extension().is_none() {
+        path.set_extension(mix.format.extension());
+    }
+    render(
+        &name,
+        &tracks,
+        &path,
+        mix.format,
+        mix.crossfade,
+        replaygain,
+        progress,
+    )?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mix(crossfade_frames: usize, tracks: &[Vec<f32>], fades: &[bool]) -> (Vec<f32>, Vec<u64>) {
+        let mut out = Vec::new();
+        let mut starts = Vec::new();
+        let mut mixer = Mixer::new(crossfade_frames, |s: &[f32]| {
+            out.extend_from_slice(s);
+            Ok(())
+        });
+        for (track, fade) in tracks.iter().zip(fades) {
+            starts.push(mixer.start_track(*fade).unwrap());
+            for chunk in track.chunks(4) {
+                mixer.add(chunk).unwrap();
+            }
+            mixer.end_track().unwrap();
+        }
+        mixer.finish().unwrap();
+        (out, starts)
+    }
+
+    #[test]
+    fn test_gapless() {
+        let a = vec![1.0; 10];
+        let b = vec![2.0; 6];
+        let (out, starts) = mix(2, &[a, b], &[false, false]);
+        assert_eq!(out, [vec![1.0; 10], vec![2.0; 6]].concat());
+        assert_eq!(starts, vec![0, 5]);
+    }
+
+    #[test]
+    fn test_path_in() {
+        let dir = Path::new("/srv/mixes");
+        assert_eq!(
+            path_in(dir, "party/mix.opus"),
+            Ok(PathBuf::from("/srv/mixes/party/mix.opus"))
+        );
+        assert!(path_in(dir, "").is_err());
+        assert!(path_in(dir, "/etc/passwd").is_err());
+        assert!(path_in(dir, "../mix").is_err());
+        assert!(path_in(dir, "party/../../mix").is_err());
+    }
+
+    #[test]
+    fn test_crossfade() {
+        let a = vec![1.0; 10];
+        let b = vec![0.0; 10];
+        let (out, starts) = mix(2, &[a, b], &[false, true]);
+        // the last two frames of a fade into the first two frames of b
+        assert_eq!(
+            out,
+            [vec![1.0; 6], vec![1.0, 1.0, 0.5, 0.5], vec![0.0; 6]].concat()
+        );
+        assert_eq!(starts, vec![0, 3]);
+    }
+
+    #[test]
+    fn test_crossfade_short_track() {
+        let a = vec![1.0; 8];
+        let b = vec![0.0; 2];
+        let (out, starts) = mix(3, &[a, b], &[false, true]);
+        // b ends in the fade, so the rest of a plays out
+        assert_eq!(out, vec![1.0; 8]);
+        assert_eq!(starts, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_cue_position() {
+        assert_eq!(cue_position(0), "00:00:00");
+        assert_eq!(cue_position(RATE * 61 + RATE / 2), "01:01:37");
+        assert_eq!(cue_position(RATE * 3600), "60:00:00");
+    }
+}
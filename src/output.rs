@@ -0,0 +1,118 @@
+use gstreamer::prelude::*;
+use std::path::Path;
+use viola_common::{AudioOutput, MixFormat, OutputDeviceJson};
+
+use crate::mix;
+
+/// the name of the sink element in the audio sink bin
+pub(crate) const SINK_NAME: &str = "sink";
+/// device properties that stay the same across restarts, the first one a device has is its id
+const ID_PROPERTIES: [&str; 4] = ["node.name", "device.name", "object.path", "api.alsa.path"];
+
+/// returns all audio sink devices GStreamer can find
+fn devices() -> Result<Vec<gstreamer::Device>, String> {
+    let monitor = gstreamer::DeviceMonitor::new();
+    monitor.add_filter(Some("Audio/Sink"), None);
+    monitor
+        .start()
+        .map_err(|e| format!("Could not start device monitor: {}", e))?;
+    let devices = monitor.devices().into_iter().collect();
+    monitor.stop();
+    Ok(devices)
+}
+
+/// the id we select `device` by, its display name if it has none of the `ID_PROPERTIES`
+fn device_id(device: &gstreamer::Device) -> String {
+    device
+        .properties()
+        .and_then(|props| {
+            ID_PROPERTIES
+                .iter()
+                .find_map(|key| props.get::<String>(*key).ok())
+        })
+        .unwrap_or_else(|| device.display_name().to_string())
+}
+
+/// can elements of `factory` play audio, we only create sinks from a request
+fn is_audio_sink(factory: &gstreamer::ElementFactory) -> bool {
+    factory.name() == "fakesink" || factory.klass().contains("Sink/Audio")
+}
+
+/// lists the audio output devices
+pub(crate) fn list_devices() -> Result<Vec<OutputDeviceJson>, String> {
+    Ok(devices()?
+        .iter()
+        .map(|d| OutputDeviceJson {
+            id: device_id(d),
+            name: d.display_name().to_string(),
+            class: d.device_class().to_string(),
+        })
+        .collect())
+}
+
+/// Creates a bin that encodes into `path` below the `output_dir` preference.
+/// The file is opened for appending so restarting the player keeps the earlier tracks.
+fn make_file_sink(path: &str, format: MixFormat) -> Result<gstreamer::Element, String> {
+    if format == MixFormat::Flac {
+        return Err(String::from(
+            "FLAC streams cannot be appended to each other, use Opus or MP3",
+        ));
+    }
+    let dir = crate::utils::load_preferences()
+        .get("output_dir")
+        .cloned()
+        .ok_or_else(|| String::from("Set output_dir in the preferences to play into a file"))?;
+    let path = mix::path_in(Path::new(&dir), path)?;
+    let bin = gstreamer::parse::bin_from_description(
+        &format!(
+            "audioconvert ! audioresample ! {} ! filesink name=filesink append=true sync=true",
+            mix::encoder_description(format)
+        ),
+        true,
+    )
+    .map_err(|e| format!("Could not create encoder: {}", e))?;
+    bin.by_name("filesink")
+        .ok_or_else(|| String::from("Could not find filesink"))?
+        .set_property("location", &*path.to_string_lossy());
+    bin.set_property("name", SINK_NAME);
+    Ok(bin.upcast())
+}
+
+/// creates the sink element for `output`
+pub(crate) fn make_sink(output: &AudioOutput) -> Result<gstreamer::Element, String> {
+    match output {
+        AudioOutput::Auto => gstreamer::ElementFactory::make("autoaudiosink")
+            .name(SINK_NAME)
+            .build()
+            .map_err(|e| format!("Could not create autoaudiosink: {}", e)),
+        AudioOutput::Device(id) => devices()?
+            .into_iter()
+            .find(|d| device_id(d) == *id)
+            .ok_or_else(|| format!("Could not find device {}", id))?
+            .create_element(Some(SINK_NAME))
+            .map_err(|e| format!("Could not create sink for {}: {}", id, e)),
+        AudioOutput::Sink { element, device } => {
+            let factory = gstreamer::ElementFactory::find(element)
+                .filter(is_audio_sink)
+                .ok_or_else(|| format!("{} is not an audio sink", element))?;
+            let sink = factory
+                .create()
+                .name(SINK_NAME)
+                .build()
+                .map_err(|e| format!("Could not create {}: {}", element, e))?;
+            if let Some(device) = device {
+                if !sink.has_property("device", Some(String::static_type())) {
+                    return Err(format!("{} does not take a device", element));
+                }
+                sink.set_property("device", device);
+            }
+            Ok(sink)
+        }
+        AudioOutput::Null => gstreamer::ElementFactory::make("fakesink")
+            .name(SINK_NAME)
+            .property("sync", true)
+            .build()
+            .map_err(|e| format!("Could not create fakesink: {}", e)),
+        AudioOutput::File { path, format } => make_file_sink(path, *format),
+    }
+}
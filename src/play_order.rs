@@ -0,0 +1,180 @@
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use viola_common::Track;
+
+/// The order we play the tracks of a playlist in, without changing the playlist itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PlayOrder {
+    /// the positions in the playlist in the order we play them
+    order: Vec<usize>,
+}
+
+impl PlayOrder {
+    /// A random order over `len` tracks that starts with `start`
+    pub(crate) fn shuffled(len: usize, start: usize) -> Self {
+        let mut order: Vec<usize> = (0..len).filter(|i| *i != start).collect();
+        order.shuffle(&mut rand::thread_rng());
+        if start < len {
+            order.insert(0, start);
+        }
+        PlayOrder { order }
+    }
+
+    /// A random order of the albums in `tracks` that plays every album in tracknumber order.
+    /// The album of `start` comes first, tracks without an album are an album on their own.
+    /// Tracks belong to the same album if they share the album cover, without one if album and artist match.
+    pub(crate) fn album_shuffled(tracks: &[Track], start: usize) -> Self {
+        let mut albums: Vec<Vec<usize>> = Vec::new();
+        let mut album_index: HashMap<(Option<&str>, &str, &str), usize> = HashMap::new();
+        for (i, track) in tracks.iter().enumerate() {
+            if track.album.is_empty() {
+                albums.push(vec![i]);
+            } else {
+                let key = match track.albumpath.as_deref() {
+                    Some(albumpath) => (Some(albumpath), "", ""),
+                    None => (None, track.album.as_str(), track.artist.as_str()),
+                };
+                let index = *album_index.entry(key).or_insert_with(|| {
+                    albums.push(Vec::new());
+                    albums.len() - 1
+                });
+                albums[index].push(i);
+            }
+        }
+        for album in &mut albums {
+            // the sort is stable, so tracks without a tracknumber keep their playlist order at the end
+            album.sort_by_key(|i| tracks[*i].tracknumber.unwrap_or(i32::MAX));
+        }
+        albums.shuffle(&mut rand::thread_rng());
+        if let Some(index) = albums.iter().position(|album| album.contains(&start)) {
+            let album = albums.remove(index);
+            albums.insert(0, album);
+        }
+        PlayOrder {
+            order: albums.concat(),
+        }
+    }
+
+    /// does this order cover a playlist with `len` tracks
+    pub(crate) fn fits(&self, len: usize) -> bool {
+        self.order.len() == len
+    }
+
+    /// the position we play first
+    pub(crate) fn first(&self) -> Option<usize> {
+        self.order.first().copied()
+    }
+
+    /// the position we play after `current`, None if `current` is the last one
+    pub(crate) fn next(&self, current: usize) -> Option<usize> {
+        let index = self.order.iter().position(|i| *i == current)?;
+        self.order.get(index + 1).copied()
+    }
+
+    /// the position we played before `current`, None if `current` is the first one
+    pub(crate) fn previous(&self, current: usize) -> Option<usize> {
+        let index = self.order.iter().position(|i| *i == current)?;
+        index.checked_sub(1).map(|i| self.order[i])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shuffled_is_permutation() {
+        let order = PlayOrder::shuffled(50, 17);
+        assert!(order.fits(50));
+        assert_eq!(order.first(), Some(17));
+        let mut positions = order.order.clone();
+        positions.sort_unstable();
+        assert_eq!(positions, (0..50).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_walk_forward_and_back() {
+        let order = PlayOrder::shuffled(20, 3);
+        let mut walked = vec![3];
+        while let Some(i) = order.next(*walked.last().unwrap()) {
+            walked.push(i);
+        }
+        assert_eq!(walked, order.order);
+
+        let mut current = *walked.last().unwrap();
+        for expected in walked.iter().rev().skip(1) {
+            current = order.previous(current).unwrap();
+            assert_eq!(current, *expected);
+        }
+        assert_eq!(order.previous(current), None);
+    }
+
+    fn track(album: &str, tracknumber: Option<i32>) -> Track {
+        Track {
+            id: 0,
+            title: String::from("Title"),
+            artist: String::from("Artist"),
+            album: String::from(album),
+            genre: String::from("Genre"),
+            tracknumber,
+            year: None,
+            path: String::from("/music/track.mp3"),
+            length: 1,
+            albumpath: None,
+            playcount: None,
+        }
+    }
+
+    #[test]
+    fn test_album_shuffled() {
+        let tracks = vec![
+            track("A", Some(2)),
+            track("B", Some(1)),
+            track("A", Some(1)),
+            track("", None),
+            track("B", None),
+            track("A", Some(3)),
+            track("B", Some(2)),
+        ];
+        let order = PlayOrder::album_shuffled(&tracks, 6);
+        assert!(order.fits(tracks.len()));
+        assert_eq!(order.order[..3], [1, 6, 4]);
+
+        let album_a = order.order.iter().position(|i| *i == 2).unwrap();
+        assert_eq!(order.order[album_a..album_a + 3], [2, 0, 5]);
+        assert!(order.order.contains(&3));
+    }
+
+    #[test]
+    fn test_album_shuffled_same_title() {
+        let by = |artist: &str, albumpath: Option<&str>, tracknumber| Track {
+            artist: String::from(artist),
+            albumpath: albumpath.map(String::from),
+            ..track("Greatest Hits", Some(tracknumber))
+        };
+        let tracks = vec![
+            by("X", None, 1),
+            by("Y", None, 1),
+            by("X", None, 2),
+            by("Y", None, 2),
+            by("Z", Some("/music/z1/cover.jpg"), 1),
+            by("Z", Some("/music/z2/cover.jpg"), 1),
+            by("Z", Some("/music/z1/cover.jpg"), 2),
+            by("Z", Some("/music/z2/cover.jpg"), 2),
+        ];
+        for (start, album) in [(0, [0, 2]), (3, [1, 3]), (6, [4, 6]), (5, [5, 7])] {
+            let order = PlayOrder::album_shuffled(&tracks, start);
+            assert!(order.fits(tracks.len()));
+            assert_eq!(order.order[..2], album);
+        }
+    }
+
+    #[test]
+    fn test_shuffled_empty() {
+        let order = PlayOrder::shuffled(0, 0);
+        assert!(order.fits(0));
+        assert_eq!(order.first(), None);
+        assert_eq!(order.next(0), None);
+    }
+}
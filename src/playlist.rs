@@ -1,9 +1,9 @@
 use diesel::{Associations, Identifiable, Insertable, Queryable};
-use viola_common::schema::{playlists, playlisttracks};
+use viola_common::schema::{playlists, playliststations, playlisttracks};
 
 use crate::loaded_playlist::LoadedPlaylist;
 use crate::types::DBPool;
-use viola_common::Track;
+use viola_common::{Station, Track};
 
 #[derive(Identifiable, Queryable)]
 pub(crate) struct Playlist {
@@ -37,19 +37,44 @@ pub(crate) struct NewPlaylistTrack {
     pub playlist_order: i32,
 }
 
-fn get_ordering((t, pt): &(Track, PlaylistTrack)) -> (i32, &Track) {
-    (pt.playlist_order, t)
+#[derive(Identifiable, Queryable, Associations)]
+#[diesel(table_name = playliststations, belongs_to(Station, foreign_key = station_id), belongs_to(Playlist, foreign_key = playlist_id))]
+pub(crate) struct PlaylistStation {
+    id: i32,
+    playlist_id: i32,
+    station_id: i32,
+    playlist_order: i32,
 }
 
-fn only_tracks<'a>(&(_, t): &'a (i32, &Track)) -> &'a Track {
-    t
+#[derive(Debug, Insertable)]
+#[diesel(table_name = playliststations)]
+pub(crate) struct NewPlaylistStation {
+    pub playlist_id: i32,
+    pub station_id: i32,
+    pub playlist_order: i32,
 }
 
-fn create_loaded_from_playlist(pl: &Playlist, r: &[(Track, PlaylistTrack)]) -> LoadedPlaylist {
-    let mut unsorted = r.iter().map(get_ordering).collect::<Vec<(i32, &Track)>>();
-    unsorted.sort_unstable_by(|&(i, _), &(j, _)| i.cmp(&j));
+fn get_ordering((t, pt): &(Track, PlaylistTrack)) -> (i32, Track) {
+    (pt.playlist_order, t.clone())
+}
+
+fn get_station_ordering((s, ps): &(Station, PlaylistStation)) -> (i32, Track) {
+    (ps.playlist_order, Track::from(s.clone()))
+}
 
-    let sorted = unsorted.iter().map(only_tracks).cloned().collect();
+fn create_loaded_from_playlist(
+    pl: &Playlist,
+    r: &[(Track, PlaylistTrack)],
+    s: &[(Station, PlaylistStation)],
+) -> LoadedPlaylist {
+    let mut unsorted = r
+        .iter()
+        .map(get_ordering)
+        .chain(s.iter().map(get_station_ordering))
+        .collect::<Vec<(i32, Track)>>();
+    unsorted.sort_unstable_by(|(i, _), (j, _)| i.cmp(j));
+
+    let sorted = unsorted.into_iter().map(|(_, t)| t).collect();
     LoadedPlaylist {
         id: pl.id,
         name: pl.name.clone(),
@@ -63,6 +88,7 @@ pub(crate) fn restore_playlists(db: &DBPool) -> Vec<LoadedPlaylist> {
     use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
     use viola_common::schema::playlists::dsl::*;
     use viola_common::schema::playlisttracks::dsl::*;
+    use viola_common::schema::stations::dsl::stations;
     use viola_common::schema::tracks::dsl::*;
 
     let pls = playlists
@@ -76,8 +102,13 @@ pub(crate) fn restore_playlists(db: &DBPool) -> Vec<LoadedPlaylist> {
                 .filter(playlist_id.eq(pl.id))
                 .load(&mut *db.lock())
                 .expect("Error restoring a playlist");
+            let s: Vec<(Station, PlaylistStation)> = stations
+                .inner_join(playliststations::table)
+                .filter(playliststations::playlist_id.eq(pl.id))
+                .load(&mut *db.lock())
+                .expect("Error restoring the stations of a playlist");
 
-            create_loaded_from_playlist(pl, &t)
+            create_loaded_from_playlist(pl, &t, &s)
         })
         .collect()
 }
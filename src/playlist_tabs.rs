@@ -1,18 +1,20 @@
 use log::info;
 use parking_lot::RwLock;
 use serde::Serialize;
-use viola_common::Track;
+use viola_common::{PlaylistItemJson, Track};
+use std::collections::HashSet;
 use std::ops::DerefMut;
 use std::sync::Arc;
-use std::{cmp::min, path::PathBuf};
+use std::{
+    cmp::min,
+    path::{Path, PathBuf},
+};
 
 use crate::loaded_playlist::{
     LoadedPlaylist, LoadedPlaylistExt, PlaylistControls, SavePlaylistExt,
 };
 use crate::playlist::restore_playlists;
 use crate::types::*;
-use preferences::Preferences;
-use preferences::PreferencesMap;
 
 /// Holding all playlisttabs
 #[derive(Debug, Serialize)]
@@ -20,6 +22,8 @@ pub(crate) struct PlaylistTabs {
     current_pl: usize,
     current_playing_in: usize,
     pub(crate) pls: Vec<LoadedPlaylistPtr>,
+    /// paths of the tracks the validation found missing
+    missing: HashSet<String>,
 }
 
 /// load the playlisttabs from the database
@@ -34,21 +38,35 @@ pub(crate) fn load(pool: &DBPool) -> Result<PlaylistTabsPtr, diesel::result::Err
         current_pl: 0,
         current_playing_in: 0,
         pls: converted_pls,
+        missing: HashSet::new(),
     }));
     pls_struct.restore_tab_position();
     Ok(pls_struct)
 }
 
+/// playlisttabs with `pls` that are not backed by the database
+#[cfg(test)]
+pub(crate) fn from_playlists(pls: Vec<LoadedPlaylist>) -> PlaylistTabsPtr {
+    Arc::new(parking_lot::RwLock::new(PlaylistTabs {
+        current_pl: 0,
+        current_playing_in: 0,
+        pls,
+        missing: HashSet::new(),
+    }))
+}
+
 pub(crate) trait PlaylistTabsExt {
     /// Add a loaded playlists to the tab structure
     fn add(&self, _: LoadedPlaylist);
+    /// appends a track to the end of the current tab
+    fn append(&self, _: Track);
     /// execute f on the current playlist
     fn current<T>(&self, f: fn(&LoadedPlaylistPtr) -> T) -> T;
     /// same but for mut
     fn current_mut<T>(&self, f: fn(&mut LoadedPlaylist) -> T) -> T;
     /// delete the playlist given by item
     fn delete(&self, _: &DBPool, _: usize);
-    /// produces the json string corresponding to the items
+    /// produces the json string corresponding to the items, flagging the ones we found missing
     fn items_json(&self) -> String;
     fn items_for_json(&self, index: usize) -> String;
     /// gives the current selected tab
@@ -57,6 +75,10 @@ pub(crate) trait PlaylistTabsExt {
     fn current_playing_in(&self) -> usize;
     /// update the current playing track in
     fn update_current_playing_in(&self);
+    /// gives the position and the track in the tab we are playing in
+    fn playing(&self) -> Option<(usize, Track)>;
+    /// gives the name of the tab at index
+    fn tab_name(&self, index: usize) -> Option<String>;
     /// set the current tab to index
     fn set_tab(&self, index: usize);
     /// restore the current selected tab (the index) from the database
@@ -65,6 +87,11 @@ pub(crate) trait PlaylistTabsExt {
     fn save_tab_position(&self);
     ///
     fn update_current_playcount(&self);
+    /// update the playcount of the track with `id` in all tabs, only in the datastructure
+    fn update_playcount(&self, id: i32);
+    /// checks if the files of the tracks at `positions` in tab `index` exist.
+    /// Returns the missing positions and if we learned something new about these tracks.
+    fn validate(&self, index: usize, positions: &[usize]) -> (Vec<usize>, bool);
 }
 
 impl PlaylistTabsExt for PlaylistTabsPtr {
@@ -72,6 +99,14 @@ impl PlaylistTabsExt for PlaylistTabsPtr {
         self.write().pls.push(lp);
     }
 
+    fn append(&self, track: Track) {
+        let mut cur = self.write();
+        let i = cur.current_pl;
+        if let Some(pl) = cur.pls.get_mut(i) {
+            pl.items.push(track);
+        }
+    }
+
     fn current<T>(&self, f: fn(&LoadedPlaylist) -> T) -> T {
         let i = self.read().current_pl;
         f(self.as_ref().read().pls.get(i).as_ref().unwrap())
@@ -113,9 +148,15 @@ impl PlaylistTabsExt for PlaylistTabsPtr {
     fn items_for_json(&self, index: usize) -> String {
         let cur = self.read();
         let pl = cur.pls.get(index).unwrap();
-        let items = &pl.items;
-        serde_json::to_string::<Vec<viola_common::Track>>(items)
-            .expect("Error in serializing")
+        let items = pl
+            .items
+            .iter()
+            .map(|t| PlaylistItemJson {
+                track: t.clone(),
+                missing: cur.missing.contains(&t.path),
+            })
+            .collect::<Vec<PlaylistItemJson>>();
+        serde_json::to_string::<Vec<PlaylistItemJson>>(&items).expect("Error in serializing")
     }
 
     fn current_tab(&self) -> usize {
@@ -131,6 +172,19 @@ impl PlaylistTabsExt for PlaylistTabsPtr {
         self.write().current_playing_in = cur;
     }
 
+    fn playing(&self) -> Option<(usize, Track)> {
+        let cur = self.read();
+        cur.pls.get(cur.current_playing_in).and_then(|pl| {
+            pl.items
+                .get(pl.current_position)
+                .map(|t| (pl.current_position, t.clone()))
+        })
+    }
+
+    fn tab_name(&self, index: usize) -> Option<String> {
+        self.read().pls.get(index).map(|pl| pl.name.clone())
+    }
+
     fn set_tab(&self, index: usize) {
         let max = self.read().pls.len();
         self.write().current_pl = std::cmp::min(max - 1, index);
@@ -138,13 +192,10 @@ impl PlaylistTabsExt for PlaylistTabsPtr {
     }
 
     fn restore_tab_position(&self) {
-        let mut prefs_file =
-            crate::utils::get_config_file(&crate::utils::ConfigWriteMode::Read).unwrap();
         //we need to split this because of how the allocation of the locks work
         let val = min(
-            PreferencesMap::<String>::load_from(&mut prefs_file)
-                .ok()
-                .and_then(|m| m.get("tab").cloned())
+            crate::utils::load_preferences()
+                .get("tab")
                 .and_then(|t| t.parse::<usize>().ok())
                 .unwrap_or(0),
             self.read().pls.len() - 1,
@@ -155,17 +206,8 @@ impl PlaylistTabsExt for PlaylistTabsPtr {
 
     fn save_tab_position(&self) {
         info!("Saving tab position");
-        let mut prefs = {
-            let mut prefs_file =
-                crate::utils::get_config_file(&crate::utils::ConfigWriteMode::Read).unwrap();
-            PreferencesMap::<String>::load_from(&mut prefs_file).expect("Error in loading prefs")
-        };
-        prefs.insert(String::from("tab"), self.read().current_pl.to_string());
-        let mut file_write =
-            crate::utils::get_config_file(&crate::utils::ConfigWriteMode::Write).unwrap();
-        prefs
-            .save_to(&mut file_write)
-            .expect("Error in writing prefs");
+        let tab = self.read().current_pl.to_string();
+        crate::utils::save_preference("tab", tab).expect("Error in writing prefs");
     }
 
     fn update_current_playcount(&self) {
@@ -176,18 +218,71 @@ impl PlaylistTabsExt for PlaylistTabsPtr {
             .unwrap()
             .update_current_playcount();
     }
+
+    fn update_playcount(&self, id: i32) {
+        for pl in self.write().pls.iter_mut() {
+            for item in pl.items.iter_mut().filter(|t| t.library_id() == Some(id)) {
+                item.playcount = Some(item.playcount.unwrap_or(0) + 1);
+            }
+        }
+    }
+
+    fn validate(&self, index: usize, positions: &[usize]) -> (Vec<usize>, bool) {
+        // we do not hold the lock while we look at the files
+        let paths = {
+            let cur = self.read();
+            let Some(pl) = cur.pls.get(index) else {
+                return (Vec::new(), false);
+            };
+            positions
+                .iter()
+                .filter_map(|i| pl.items.get(*i).map(|t| (*i, t)))
+                .filter(|(_, t)| !t.is_stream())
+                .map(|(i, t)| (i, t.path.clone()))
+                .collect::<Vec<(usize, String)>>()
+        };
+        let checked = paths
+            .into_iter()
+            .map(|(i, path)| (i, Path::new(&path).exists(), path))
+            .collect::<Vec<(usize, bool, String)>>();
+
+        let mut changed = false;
+        let mut cur = self.write();
+        for (_, exists, path) in &checked {
+            changed |= if *exists {
+                cur.missing.remove(path)
+            } else {
+                cur.missing.insert(path.clone())
+            };
+        }
+        let missing = checked
+            .into_iter()
+            .filter(|(_, exists, _)| !exists)
+            .map(|(i, _, _)| i)
+            .collect();
+        (missing, changed)
+    }
 }
 
 pub(crate) trait LoadedPlaylistExtImut {
     /// Returns the current track
     fn get_current_track(&self) -> Track;
 
+    /// Returns the track at position `i`
+    fn get_track_at(&self, i: usize) -> Option<Track>;
+
     /// get the added time of the whole playlist
     fn get_playlist_full_time(&self) -> i64;
 
     /// returns the raw current_position
     fn current_position(&self) -> usize;
 
+    /// returns the number of tracks in the current playlist
+    fn track_count(&self) -> usize;
+
+    /// returns a copy of all tracks in the current playlist
+    fn tracks(&self) -> Vec<Track>;
+
     /// get the remaining length, ignoring already played tracks and the current playling track
     fn get_remaining_length(&self) -> u64;
 
@@ -204,6 +299,14 @@ impl LoadedPlaylistExtImut for PlaylistTabsPtr {
         //value.get_current_track()
     }
 
+    fn get_track_at(&self, i: usize) -> Option<Track> {
+        let cur = self.read();
+        cur.pls
+            .get(cur.current_pl)
+            .and_then(|pl| pl.items.get(i))
+            .cloned()
+    }
+
     fn get_playlist_full_time(&self) -> i64 {
         self.current(LoadedPlaylistExt::get_playlist_full_time)
     }
@@ -212,6 +315,18 @@ impl LoadedPlaylistExtImut for PlaylistTabsPtr {
         self.current(LoadedPlaylistExt::current_position)
     }
 
+    fn track_count(&self) -> usize {
+        let cur = self.read();
+        cur.pls.get(cur.current_pl).map_or(0, |pl| pl.items.len())
+    }
+
+    fn tracks(&self) -> Vec<Track> {
+        let cur = self.read();
+        cur.pls
+            .get(cur.current_pl)
+            .map_or_else(Vec::new, |pl| pl.items.clone())
+    }
+
     //fn items(&self) -> RwLockReadGuardRef<LoadedPlaylist, Vec<crate::db::Track>> {
     //    self.current(LoadedPlaylistExt::items)
     //}
@@ -243,6 +358,14 @@ pub(crate) trait PlaylistControlsImut {
     fn delete_range(&self, _: std::ops::Range<usize>);
     /// sets the position to the next one or zero if we are eol. Returns None if we are eol otherwise the position.
     fn next_or_eol(&self) -> Option<usize>;
+    /// returns the position `next_or_eol` would go to without changing anything
+    fn peek_next(&self) -> Option<usize>;
+    /// Get track path at position `i` in the current playlist
+    fn get_path_at(&self, i: usize) -> Option<PathBuf>;
+    /// Get track uri at position `i` in the current playlist
+    fn get_uri_at(&self, i: usize) -> Option<String>;
+    /// can we play the track at position `i` in the current playlist
+    fn is_available_at(&self, i: usize) -> bool;
 }
 
 impl PlaylistControlsImut for PlaylistTabsPtr {
@@ -274,6 +397,27 @@ impl PlaylistControlsImut for PlaylistTabsPtr {
         self.update_current_playing_in();
         self.current_mut(PlaylistControls::next_or_eol)
     }
+
+    fn peek_next(&self) -> Option<usize> {
+        self.current(PlaylistControls::peek_next)
+    }
+
+    fn get_path_at(&self, i: usize) -> Option<PathBuf> {
+        let cur = self.read();
+        cur.pls.get(cur.current_pl).and_then(|pl| pl.get_path_at(i))
+    }
+
+    fn get_uri_at(&self, i: usize) -> Option<String> {
+        let cur = self.read();
+        cur.pls.get(cur.current_pl).and_then(|pl| pl.get_uri_at(i))
+    }
+
+    fn is_available_at(&self, i: usize) -> bool {
+        let cur = self.read();
+        cur.pls
+            .get(cur.current_pl)
+            .is_some_and(|pl| pl.is_available_at(i))
+    }
 }
 
 impl SavePlaylistExt for PlaylistTabsPtr {
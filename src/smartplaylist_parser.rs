@@ -21,6 +21,8 @@ pub(crate) struct SmartPlaylist {
     include_query: Vec<IncludeTag>,
     exclude_query: Vec<ExcludeTag>,
     create_m3u: bool,
+    /// remember the position in every track of this playlist
+    pub remember_position: bool,
 }
 
 #[derive(Deserialize, Debug)]
@@ -33,6 +35,7 @@ struct SmartPlaylistParsed {
     name: String,
     random: Option<bool>,
     create_m3u: Option<bool>,
+    remember_position: Option<bool>,
     dir_exclude: Option<Vec<String>>,
     dir_include: Option<Vec<String>>,
     album_include: Option<Vec<String>>,
@@ -90,6 +93,7 @@ impl From<SmartPlaylistParsed> for SmartPlaylist {
             name: smp.name,
             create_m3u: smp.create_m3u.unwrap_or(false),
             random: smp.random.unwrap_or(false),
+            remember_position: smp.remember_position.unwrap_or(false),
             include_query,
             exclude_query,
         }
@@ -0,0 +1,90 @@
+use crate::types::DBPool;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use viola_common::schema::{playliststations, stations};
+use viola_common::{NewStationJson, Station};
+
+/// returns all stations ordered by name
+pub(crate) fn list(db: &DBPool) -> Result<Vec<Station>, String> {
+    stations::table
+        .order(stations::name.asc())
+        .load::<Station>(&mut *db.lock())
+        .map_err(|e| format!("Could not load stations: {}", e))
+}
+
+/// returns the station with `id`
+pub(crate) fn get(db: &DBPool, id: i32) -> Option<Station> {
+    stations::table
+        .find(id)
+        .first::<Station>(&mut *db.lock())
+        .ok()
+}
+
+/// adds `station` and returns it with its id
+pub(crate) fn add(db: &DBPool, station: &NewStationJson) -> Result<Station, String> {
+    if !(station.url.starts_with("http://") || station.url.starts_with("https://")) {
+        return Err(format!("{} is not an http(s) url", station.url));
+    }
+    let mut db = db.lock();
+    diesel::insert_into(stations::table)
+        .values(station)
+        .execute(&mut *db)
+        .map_err(|e| format!("Could not add station: {}", e))?;
+    stations::table
+        .filter(stations::url.eq(&station.url))
+        .first::<Station>(&mut *db)
+        .map_err(|e| format!("Could not load added station: {}", e))
+}
+
+/// removes the station with `id` from the stations and all saved playlists
+pub(crate) fn remove(db: &DBPool, id: i32) -> Result<(), String> {
+    let mut db = db.lock();
+    diesel::delete(playliststations::table.filter(playliststations::station_id.eq(id)))
+        .execute(&mut *db)
+        .map_err(|e| format!("Could not remove station from playlists: {}", e))?;
+    diesel::delete(stations::table.find(id))
+        .execute(&mut *db)
+        .map(|_| ())
+        .map_err(|e| format!("Could not remove station: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::test_db;
+    use viola_common::Track;
+
+    fn station(name: &str, url: &str) -> NewStationJson {
+        NewStationJson {
+            name: String::from(name),
+            url: String::from(url),
+        }
+    }
+
+    #[test]
+    fn test_add_list_remove() {
+        let db = test_db();
+        let radio = add(&db, &station("Radio", "https://example.org/radio.mp3")).unwrap();
+        let jazz = add(&db, &station("Jazz", "http://example.org/jazz")).unwrap();
+        assert_eq!(radio.name, "Radio");
+        assert_eq!(get(&db, jazz.id), Some(jazz.clone()));
+        assert_eq!(list(&db).unwrap(), vec![jazz.clone(), radio.clone()]);
+
+        let track = Track::from(jazz.clone());
+        assert!(track.is_stream());
+        assert_eq!(track.station_id(), Some(jazz.id));
+        assert_eq!(track.library_id(), None);
+        assert!(crate::track_server::get(&db, track.id).is_none());
+
+        remove(&db, jazz.id).unwrap();
+        assert_eq!(get(&db, jazz.id), None);
+        assert_eq!(list(&db).unwrap(), vec![radio]);
+    }
+
+    #[test]
+    fn test_add_rejects_files() {
+        let db = test_db();
+        assert!(add(&db, &station("File", "/music/a.mp3")).is_err());
+        assert!(add(&db, &station("File", "file:///music/a.mp3")).is_err());
+        assert!(list(&db).unwrap().is_empty());
+    }
+}
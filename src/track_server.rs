@@ -0,0 +1,208 @@
+use diesel::{QueryDsl, RunQueryDsl};
+use log::{info, warn};
+use serde::Deserialize;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio::sync::mpsc;
+use viola_common::schema::tracks;
+use viola_common::{MixFormat, Track};
+use warp::http::{Response, StatusCode};
+use warp::hyper::Body;
+
+use crate::mix;
+use crate::types::DBPool;
+
+/// bytes we read from a file at once
+const CHUNK: usize = 64 * 1024;
+/// how many encoded buffers a transcoding may be ahead of the client
+const TRANSCODE_BACKLOG: usize = 16;
+
+/// The query of a track request, a format transcodes the track
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct TrackQuery {
+    pub(crate) format: Option<String>,
+}
+
+/// returns the library track with `id`, the negative ids of stations are no library tracks
+pub(crate) fn get(db: &DBPool, id: i32) -> Option<Track> {
+    if id < 0 {
+        return None;
+    }
+    tracks::table.find(id).first::<Track>(&mut *db.lock()).ok()
+}
+
+/// the content type of the file at `path`, guessed from its extension
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("mp3") => "audio/mpeg",
+        Some("flac") => "audio/flac",
+        Some("ogg" | "oga" | "opus") => "audio/ogg",
+        Some("m4a" | "mp4" | "aac") => "audio/mp4",
+        Some("wav") => "audio/wav",
+        _ => "application/octet-stream",
+    }
+}
+
+/// the first and last byte a `Range` header asks for in a file of `len` bytes,
+/// None if it is malformed, asks for several ranges or none of the bytes are in the file
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let last = len.checked_sub(1)?;
+    if start.is_empty() {
+        // the last `end` bytes
+        let suffix = end.parse::<u64>().ok().filter(|s| *s > 0)?;
+        return Some((len.saturating_sub(suffix), last));
+    }
+    let start = start.parse::<u64>().ok()?;
+    let end = if end.is_empty() {
+        last
+    } else {
+        end.parse::<u64>().ok()?.min(last)
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// a body that reads `reader` in chunks
+fn reader_body<R: AsyncRead + Send + Unpin + 'static>(reader: R) -> Body {
+    Body::wrap_stream(futures::stream::unfold(reader, |mut reader| async move {
+        let mut buf = vec![0; CHUNK];
+        match reader.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), reader))
+            }
+            Err(e) => Some((Err(e), reader)),
+        }
+    }))
+}
+
+fn error_response(status: StatusCode, msg: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(msg))
+        .unwrap()
+}
+
+/// opens the file at `path` and returns it with its length
+async fn open(path: &Path) -> std::io::Result<(tokio::fs::File, u64)> {
+    let file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    Ok((file, len))
+}
+
+/// serves the file at `path`, only the bytes `range` asks for if there is a `Range` header
+pub(crate) async fn serve_file(path: &Path, range: Option<String>) -> Response<Body> {
+    let (mut file, len) = match open(path).await {
+        Ok(opened) => opened,
+        Err(e) => {
+            warn!("Could not open {}: {}", path.display(), e);
+            return error_response(
+                StatusCode::NOT_FOUND,
+                format!("Could not open {}", path.display()),
+            );
+        }
+    };
+    let response = Response::builder()
+        .header("Content-Type", content_type(path))
+        .header("Accept-Ranges", "bytes");
+    let Some(range) = range else {
+        return response
+            .header("Content-Length", len)
+            .body(reader_body(file))
+            .unwrap();
+    };
+    let Some((start, end)) = parse_range(&range, len) else {
+        return response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("Content-Range", format!("bytes */{}", len))
+            .body(Body::empty())
+            .unwrap();
+    };
+    if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+        return error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Could not read {}: {}", path.display(), e),
+        );
+    }
+    response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header("Content-Length", end - start + 1)
+        .header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+        .body(reader_body(file.take(end - start + 1)))
+        .unwrap()
+}
+
+/// decodes the file at `path` and sends it encoded in `format` to `sender` until the receiver is gone
+fn transcode(path: &Path, format: MixFormat, sender: &mpsc::Sender<Vec<u8>>) -> Result<(), String> {
+    let pipeline = mix::make_pipeline(&format!(
+        "filesrc name=src ! decodebin ! audioconvert ! audioresample ! {} ! appsink name=sink sync=false",
+        mix::encoder_description(format)
+    ))?;
+    mix::element(&pipeline, "src")?.set_property("location", &*path.to_string_lossy());
+    let sink = mix::element(&pipeline, "sink")?;
+    mix::pull(&pipeline, &sink, &path.to_string_lossy(), &mut |data| {
+        sender
+            .blocking_send(data.to_vec())
+            .map_err(|_| String::from("The client went away"))
+    })
+}
+
+/// serves the file at `path` transcoded to `format`, we cannot seek in it
+pub(crate) fn serve_transcoded(path: PathBuf, format: MixFormat) -> Response<Body> {
+    let (sender, receiver) = mpsc::channel(TRANSCODE_BACKLOG);
+    tokio::task::spawn_blocking(move || {
+        info!("Transcoding {} to {:?}", path.display(), format);
+        if let Err(e) = transcode(&path, format, &sender) {
+            if sender.is_closed() {
+                info!("Stopped transcoding {}", path.display());
+            } else {
+                warn!("{}", e);
+            }
+        }
+    });
+    let body = futures::stream::unfold(receiver, |mut receiver| async move {
+        let data = receiver.recv().await?;
+        Some((Ok::<_, std::convert::Infallible>(data), receiver))
+    });
+    Response::builder()
+        .header("Content-Type", format.content_type())
+        .header("Cache-Control", "no-cache")
+        .body(Body::wrap_stream(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+        assert_eq!(parse_range("bytes=90-200", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-200", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("bytes=20-10", 100), None);
+        assert_eq!(parse_range("bytes=-0", 100), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), None);
+        assert_eq!(parse_range("items=0-1", 100), None);
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(Path::new("/music/a.MP3")), "audio/mpeg");
+        assert_eq!(content_type(Path::new("/music/a.opus")), "audio/ogg");
+        assert_eq!(
+            content_type(Path::new("/music/a")),
+            "application/octet-stream"
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use directories::ProjectDirs;
-use log::info;
-use std::fs::File;
+use parking_lot::Mutex;
+use preferences::{Preferences, PreferencesMap};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// held while we write the preferences, a write reads all values and writes them back
+static PREFERENCES_WRITE: Mutex<()> = Mutex::new(());
 
 pub(crate) fn get_config_dir() -> Result<std::path::PathBuf, String> {
     ProjectDirs::from("com", "narfinger", "viola")
@@ -8,22 +13,34 @@ pub(crate) fn get_config_dir() -> Result<std::path::PathBuf, String> {
         .ok_or_else(|| String::from("Could not find config dir"))
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) enum ConfigWriteMode {
-    Read,
-    Write,
+fn preferences_path() -> Result<PathBuf, String> {
+    get_config_dir().map(|p| p.join("viola_prefs.json"))
+}
+
+/// loads the preferences, returns an empty map if we cannot read them
+pub(crate) fn load_preferences() -> PreferencesMap<String> {
+    preferences_path()
+        .and_then(|p| File::open(p).map_err(|_| String::from("Could not open file")))
+        .ok()
+        .and_then(|mut f| PreferencesMap::<String>::load_from(&mut f).ok())
+        .unwrap_or_default()
+}
+
+/// writes `contents` to a temporary file and renames it to `path`, so nobody reads a half written file
+pub(crate) fn write_file(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).map_err(|e| format!("Could not write {}: {}", tmp.display(), e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Could not replace {}: {}", path.display(), e))
 }
 
-pub(crate) fn get_config_file(mode: &ConfigWriteMode) -> Result<File, String> {
-    info!("Settings file with {:?}", mode);
-    get_config_dir()
-        .map(|p| p.join("viola_prefs.json"))
-        .and_then(|f| {
-            if mode == &ConfigWriteMode::Write {
-                File::create(f)
-            } else {
-                File::open(f)
-            }
-            .map_err(|_| String::from("Could not open file"))
-        })
+/// sets `key` to `value` in the preferences while keeping all other values
+pub(crate) fn save_preference(key: &str, value: String) -> Result<(), String> {
+    let _write = PREFERENCES_WRITE.lock();
+    let mut prefs = load_preferences();
+    prefs.insert(String::from(key), value);
+    let mut json = Vec::new();
+    prefs
+        .save_to(&mut json)
+        .map_err(|_| String::from("Could not save preferences"))?;
+    write_file(&preferences_path()?, &json)
 }
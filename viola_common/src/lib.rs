@@ -5,7 +5,7 @@ extern crate diesel;
 #[macro_use]
 pub mod schema;
 #[cfg(feature = "backend")]
-use crate::schema::tracks;
+use crate::schema::{stations, tracks};
 use serde::{Deserialize, Serialize};
 use std::hash::Hash;
 
@@ -38,6 +38,70 @@ impl Hash for Track {
     }
 }
 
+impl Track {
+    /// is this an internet radio station instead of a library track, stations have negative ids
+    pub fn is_stream(&self) -> bool {
+        self.id < 0
+    }
+
+    /// the id of the track in the library, None for a station
+    pub fn library_id(&self) -> Option<i32> {
+        (!self.is_stream()).then_some(self.id)
+    }
+
+    /// the id of the station, None for a library track
+    pub fn station_id(&self) -> Option<i32> {
+        self.is_stream().then_some(-self.id)
+    }
+}
+
+/// A track in the playlist JSON, flagged if we found its file missing
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct PlaylistItemJson {
+    #[serde(flatten)]
+    pub track: Track,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub missing: bool,
+}
+
+/// An internet radio station or other http(s) stream
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "backend", derive(Identifiable, Queryable))]
+pub struct Station {
+    pub id: i32,
+    pub name: String,
+    pub url: String,
+}
+
+/// A station we want to add
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[cfg_attr(feature = "backend", derive(Insertable))]
+#[cfg_attr(feature = "backend", diesel(table_name = stations))]
+pub struct NewStationJson {
+    pub name: String,
+    pub url: String,
+}
+
+/// A station in a playlist is a track with the url as path and the station name as title.
+/// It gets the negated station id, so it never has the id of a library track.
+impl From<Station> for Track {
+    fn from(station: Station) -> Self {
+        Track {
+            id: -station.id,
+            title: station.name,
+            artist: String::new(),
+            album: String::new(),
+            genre: String::new(),
+            tracknumber: None,
+            year: None,
+            path: station.url,
+            length: 0,
+            albumpath: None,
+            playcount: None,
+        }
+    }
+}
+
 /// Actions we want to perform on gstreamer, such as playing and pausing
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
 pub enum GStreamerAction {
@@ -48,8 +112,52 @@ pub enum GStreamerAction {
     Stop,
     // This means we selected one specific track
     Play(usize),
-    Seek(u64),
-    RepeatOnce, // Repeat the current playing track after it finishes
+    Seek(u64),      // absolute position in microseconds
+    SeekBy(i64),    // relative to the current position in microseconds, negative seeks backwards
+    RepeatOnce,     // Repeat the current playing track after it finishes
+    SetVolume(u32), // in percent
+    Mute(bool),
+    SetRepeat(RepeatMode),
+    SetPlayMode(PlayMode),
+}
+
+/// What we play after the current track ends
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    Track,
+    Playlist, // start again at the beginning after the last track
+}
+
+/// The order we play the tracks of a playlist in, the playlist itself keeps its order
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum PlayMode {
+    #[default]
+    Ordered,
+    Shuffle,
+    AlbumShuffle, // albums in random order, the tracks of an album in tracknumber order
+}
+
+/// The volume in percent and if we are muted
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct VolumeJson {
+    pub volume: u32,
+    pub muted: bool,
+}
+
+/// The position we remembered in a track
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct BookmarkJson {
+    pub track: Track,
+    pub position: u64, // in seconds
+}
+
+/// A track we could not play
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct PlaybackErrorJson {
+    pub path: Option<String>,
+    pub error: String,
 }
 
 /// Messages that gstreamer sends such as the state it is going into
@@ -62,6 +170,13 @@ pub enum GStreamerMessage {
     Nop,
     ChangedDuration((u64, u64)), //in seconds
     FileNotFound,
+    VolumeChanged(VolumeJson),
+    RateChanged,
+    RepeatChanged(RepeatMode),
+    PlayModeChanged(PlayMode),
+    PlaybackError,      // the errors themselves go out as WsMessage::PlaybackError
+    Seeked(u64),        // the new position in microseconds
+    StreamTitleChanged, // the title goes out as WsMessage::StreamTitle
 }
 
 impl std::fmt::Display for GStreamerMessage {
@@ -74,6 +189,13 @@ impl std::fmt::Display for GStreamerMessage {
             GStreamerMessage::Nop => write!(f, "NOP"),
             GStreamerMessage::ChangedDuration((_, _)) => write!(f, "NOP"),
             GStreamerMessage::FileNotFound => write!(f, "FileNotFound"),
+            GStreamerMessage::VolumeChanged(_) => write!(f, "VolumeChanged"),
+            GStreamerMessage::RateChanged => write!(f, "RateChanged"),
+            GStreamerMessage::RepeatChanged(_) => write!(f, "RepeatChanged"),
+            GStreamerMessage::PlayModeChanged(_) => write!(f, "PlayModeChanged"),
+            GStreamerMessage::PlaybackError => write!(f, "PlaybackError"),
+            GStreamerMessage::Seeked(_) => write!(f, "Seeked"),
+            GStreamerMessage::StreamTitleChanged => write!(f, "StreamTitleChanged"),
         }
     }
 }
@@ -82,8 +204,14 @@ impl From<GStreamerAction> for GStreamerMessage {
     fn from(action: GStreamerAction) -> Self {
         match action {
             GStreamerAction::Pausing => GStreamerMessage::Pausing,
+            GStreamerAction::SetRepeat(mode) => GStreamerMessage::RepeatChanged(mode),
+            GStreamerAction::SetPlayMode(mode) => GStreamerMessage::PlayModeChanged(mode),
             GStreamerAction::Stop => GStreamerMessage::Stopped,
-            GStreamerAction::Seek(_) | GStreamerAction::RepeatOnce => GStreamerMessage::Nop,
+            GStreamerAction::Seek(_)
+            | GStreamerAction::SeekBy(_)
+            | GStreamerAction::RepeatOnce
+            | GStreamerAction::SetVolume(_)
+            | GStreamerAction::Mute(_) => GStreamerMessage::Nop,
             GStreamerAction::Next
             | GStreamerAction::Previous
             | GStreamerAction::Play(_)
@@ -92,8 +220,206 @@ impl From<GStreamerAction> for GStreamerMessage {
     }
 }
 
+/// An equalizer preset with the gains in dB of the ten bands, from 29Hz to 15kHz
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EqualizerPreset {
+    pub name: String,
+    pub gains: [f64; 10],
+}
+
+/// The state of the equalizer
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct EqualizerJson {
+    /// the active preset, None if the gains were changed by hand
+    pub preset: Option<String>,
+    pub gains: [f64; 10],
+    pub presets: Vec<EqualizerPreset>,
+}
+
+/// Which ReplayGain tags we use to normalize the volume
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum ReplayGainMode {
+    Off,
+    Track,
+    Album,
+    Auto, // album gain if we play an album in order, track gain otherwise
+}
+
+/// The ReplayGain settings, the gains are in dB
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ReplayGainJson {
+    pub mode: ReplayGainMode,
+    pub preamp: f64,
+    /// the gain for files without ReplayGain tags
+    pub fallback_gain: f64,
+}
+
+impl Default for ReplayGainJson {
+    fn default() -> Self {
+        ReplayGainJson {
+            mode: ReplayGainMode::Album,
+            preamp: 0.0,
+            fallback_gain: 0.0,
+        }
+    }
+}
+
+/// The sink we play to
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum AudioOutput {
+    /// let GStreamer decide
+    #[default]
+    Auto,
+    /// a device by its id as listed in the output devices
+    Device(String),
+    /// an audio sink element, i.e., pulsesink, pipewiresink or alsasink, with an optional device string
+    Sink {
+        element: String,
+        device: Option<String>,
+    },
+    /// plays silently in real time, i.e., to run without a sound card
+    Null,
+    /// encodes into the file at `path` relative to the output dir, every track is appended to it
+    File { path: String, format: MixFormat },
+}
+
+/// An audio output device GStreamer found
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct OutputDeviceJson {
+    /// what `AudioOutput::Device` takes, it stays the same across restarts
+    pub id: String,
+    pub name: String,
+    pub class: String,
+}
+
+/// When the sleep timer stops playback
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum SleepTimer {
+    /// stop after some minutes, at most a day, optionally fading out the volume before
+    Minutes { minutes: u64, fade: bool },
+    AfterTrack,
+    AfterAlbum,
+}
+
+/// The running sleep timer
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SleepTimerJson {
+    pub timer: SleepTimer,
+    /// seconds until we stop, only for `SleepTimer::Minutes`
+    pub remaining: Option<u64>,
+}
+
+/// Progress of a library scan
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum ScanMessage {
+    Started,
+    FilesFound(usize),
+    Processed(usize, usize), // processed files, all files
+    Error(String),
+    Removed(usize),
+    Finished,
+}
+
+impl std::fmt::Display for ScanMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanMessage::Started => write!(f, "Scan started"),
+            ScanMessage::FilesFound(i) => write!(f, "Found {} files", i),
+            ScanMessage::Processed(i, all) => write!(f, "Processed {}/{} files", i, all),
+            ScanMessage::Error(err) => write!(f, "Error: {}", err),
+            ScanMessage::Removed(i) => write!(f, "Removed {} old entries", i),
+            ScanMessage::Finished => write!(f, "Scan finished"),
+        }
+    }
+}
+
+/// The formats we can render a mix or encode the output in
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum MixFormat {
+    #[default]
+    Flac,
+    Opus,
+    Mp3,
+}
+
+impl MixFormat {
+    /// the file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MixFormat::Flac => "flac",
+            MixFormat::Opus => "opus",
+            MixFormat::Mp3 => "mp3",
+        }
+    }
+
+    /// the content type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            MixFormat::Flac => "audio/flac",
+            MixFormat::Opus => "audio/ogg",
+            MixFormat::Mp3 => "audio/mpeg",
+        }
+    }
+}
+
+impl std::str::FromStr for MixFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "flac" => Ok(MixFormat::Flac),
+            "opus" => Ok(MixFormat::Opus),
+            "mp3" => Ok(MixFormat::Mp3),
+            _ => Err(format!("Unknown format {}, use flac, opus or mp3", s)),
+        }
+    }
+}
+
+/// Renders the tab at `tab` into one file at `path`
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub struct MixJson {
+    pub tab: usize,
+    pub path: String,
+    #[serde(default)]
+    pub format: MixFormat,
+    /// crossfade in seconds up to 30, zero joins the tracks gaplessly
+    #[serde(default)]
+    pub crossfade: u64,
+}
+
+/// Progress of rendering a mix
+#[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum MixMessage {
+    Started(usize),          // number of tracks
+    Rendering(usize, usize), // current track, all tracks
+    Error(String),
+    Finished(String), // path of the mix
+}
+
+impl std::fmt::Display for MixMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MixMessage::Started(all) => write!(f, "Mixing {} tracks", all),
+            MixMessage::Rendering(i, all) => write!(f, "Rendering track {}/{}", i, all),
+            MixMessage::Error(err) => write!(f, "Mix error: {}", err),
+            MixMessage::Finished(path) => write!(f, "Mix written to {}", path),
+        }
+    }
+}
+
+/// Levels and spectrum of the playing audio in whole dB, so the messages stay small
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct VisualizationJson {
+    /// per channel
+    pub rms: Vec<i8>,
+    /// per channel
+    pub peak: Vec<i8>,
+    /// the magnitudes of the spectrum from the lowest to the highest frequency
+    pub bands: Vec<i8>,
+}
+
 /// Messages we send over the websocket
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WsMessage {
     PlayChanged(usize),
     CurrentTimeChanged(u64),
@@ -101,6 +427,13 @@ pub enum WsMessage {
     ReloadPlaylist,
     Ping,
     GStreamerMessage(GStreamerMessage),
+    Scan(ScanMessage),
+    Mix(MixMessage),
+    Visualization(VisualizationJson),
+    VisualizationInterval(Option<u64>), // milliseconds between the visualization data, None if it is off
+    SleepTimer(Option<SleepTimerJson>),
+    PlaybackError(PlaybackErrorJson),
+    StreamTitle(Option<String>), // what the playing stream announces, i.e., the ICY title of a radio station
 }
 
 impl From<WsMessage> for String {
@@ -6,6 +6,15 @@ table! {
     }
 }
 
+table! {
+    playliststations (id) {
+        id -> Integer,
+        playlist_id -> Integer,
+        station_id -> Integer,
+        playlist_order -> Integer,
+    }
+}
+
 table! {
     playlisttracks (id) {
         id -> Integer,
@@ -15,6 +24,21 @@ table! {
     }
 }
 
+table! {
+    stations (id) {
+        id -> Integer,
+        name -> Text,
+        url -> Text,
+    }
+}
+
+table! {
+    track_positions (track_id) {
+        track_id -> Integer,
+        position -> Integer,
+    }
+}
+
 table! {
     tracks (id) {
         id -> Integer,
@@ -31,7 +55,10 @@ table! {
     }
 }
 
+joinable!(playliststations -> playlists (playlist_id));
+joinable!(playliststations -> stations (station_id));
 joinable!(playlisttracks -> playlists (playlist_id));
 joinable!(playlisttracks -> tracks (track_id));
+joinable!(track_positions -> tracks (track_id));
 
-allow_tables_to_appear_in_same_query!(playlists, playlisttracks, tracks,);
+allow_tables_to_appear_in_same_query!(playlists, playliststations, playlisttracks, stations, track_positions, tracks,);
@@ -0,0 +1,87 @@
+use crate::button::*;
+use gloo_net::http::Request;
+use std::rc::Rc;
+use viola_common::Track;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub(crate) struct BrowserPlayerProps {
+    pub(crate) enabled: bool,
+    pub(crate) tracks: Vec<Rc<Track>>,
+    /// the position we start playing at
+    pub(crate) start: usize,
+    pub(crate) toggle_callback: Callback<()>,
+    /// called with the position of a track we played to the end
+    pub(crate) played_callback: Callback<usize>,
+}
+
+/// where the browser gets the audio of `track` from, we play stations directly
+fn source(track: &Track) -> String {
+    if track.is_stream() {
+        track.path.clone()
+    } else {
+        format!("/track/{}/", track.id)
+    }
+}
+
+/// tells the server we played the track with `id` to the end
+fn played(id: i32) {
+    wasm_bindgen_futures::spawn_local(async move {
+        Request::post(&format!("/track/{}/played/", id))
+            .send()
+            .await
+            .unwrap();
+    });
+}
+
+#[function_component(BrowserPlayer)]
+pub(crate) fn browser_player(props: &BrowserPlayerProps) -> Html {
+    // None until we played a track to the end, so we start where the server is
+    let position = use_state(|| None::<usize>);
+    let enabled = props.enabled;
+    let toggle = props.toggle_callback.clone();
+    let toggle_position = position.clone();
+    let toggle_callback = Callback::from(move |_| {
+        toggle_position.set(None);
+        toggle.emit(());
+    });
+    let text = if enabled {
+        "Play On Server"
+    } else {
+        "Play In Browser"
+    };
+
+    let current = position.unwrap_or(props.start);
+    let player = match props.tracks.get(current).filter(|_| enabled) {
+        Some(track) => {
+            let ended = {
+                let track = track.clone();
+                let played_callback = props.played_callback.clone();
+                let position = position.clone();
+                Callback::from(move |_| {
+                    if !track.is_stream() {
+                        played(track.id);
+                        played_callback.emit(current);
+                    }
+                    position.set(Some(current + 1));
+                })
+            };
+            html! {
+                <div class="col-5">
+                    <div>{format!("{} - {}", track.artist, track.title)}</div>
+                    <audio controls=true autoplay=true style="width: 100%" src={source(track)} onended={ended} />
+                </div>
+            }
+        }
+        None => html! {},
+    };
+
+    html! {
+        <div class="row" style="padding: 0.1em">
+            <div class="col-1">
+                <CallbackButton text={text} icon="/headphones.svg" btype={ButtonType::Secondary} callback={toggle_callback} />
+            </div>
+            {player}
+        </div>
+    }
+}
@@ -5,6 +5,7 @@ use yew::prelude::*;
 #[derive(Properties, PartialEq)]
 pub(crate) struct ButtonRowProps {
     pub(crate) status: GStreamerMessage,
+    pub(crate) repeat_mode: RepeatMode,
     pub(crate) repeat_once_callback: Callback<()>,
     pub(crate) refresh_play_callback: Callback<()>,
     pub(crate) sidebar_callback: Callback<()>,
@@ -24,6 +25,13 @@ pub(crate) fn buttons(props: &ButtonRowProps) -> Html {
         }
     };
 
+    // the button switches to the next mode, the new mode comes back over the websocket
+    let (repeat_text, next_repeat_mode) = match props.repeat_mode {
+        RepeatMode::Off => ("Repeat: Off", RepeatMode::Track),
+        RepeatMode::Track => ("Repeat: Track", RepeatMode::Playlist),
+        RepeatMode::Playlist => ("Repeat: Playlist", RepeatMode::Off),
+    };
+
     html! {
     <div class="row">
         <div class="col">
@@ -44,6 +52,9 @@ pub(crate) fn buttons(props: &ButtonRowProps) -> Html {
         <div class="col">
             <TransportButton text="Again" icon="/arrow-repeat.svg" btype={ButtonType::Secondary} on_click={Some(GStreamerAction::RepeatOnce)} callback = {props.repeat_once_callback.clone()} />
         </div>
+        <div class="col">
+            <TransportButton text={repeat_text} icon="/repeat.svg" btype={ButtonType::Secondary} on_click={Some(GStreamerAction::SetRepeat(next_repeat_mode))} callback={Callback::noop()} />
+        </div>
         <div class="col">
             <UrlCallButton text="Clean" icon="/trash.svg" btype={ButtonType::Danger} url_call = {"/clean/"} />
         </div>
@@ -1,4 +1,4 @@
-use std::rc::Rc;
+use std::{collections::HashSet, rc::Rc};
 
 use futures::{join, StreamExt};
 use gloo_net::{
@@ -9,8 +9,10 @@ use viola_common::*;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 
+mod browser_player;
 mod button;
 mod delete_range_dialog;
+mod meter;
 mod play_dialog;
 mod sidebar;
 mod status;
@@ -18,12 +20,16 @@ mod tabs;
 mod tracks;
 mod treeview;
 mod utils;
+mod volume;
+use browser_player::BrowserPlayer;
 use button::Buttons;
 use delete_range_dialog::DeleteRangeDialog;
+use meter::Meter;
 use sidebar::Sidebar;
 use status::Status;
 use tabs::TabsComponent;
 use tracks::TracksComponent;
+use volume::Volume;
 
 const TRACK_MAX_NUMBER: usize = 500;
 const RIDICULOUS_LARGE_TRACK_NUMBER: usize = 10000;
@@ -32,12 +38,24 @@ struct App {
     current_playing: usize,
     current_status: GStreamerMessage,
     current_tracks: Vec<Rc<viola_common::Track>>,
+    missing_tracks: Rc<HashSet<usize>>,
     current_track_time: u64,
     repeat_once: bool,
     sidebar_visible: bool,
     delete_range_visible: bool,
     playlist_tabs: PlaylistTabsJSON,
     show_full_playlist: bool,
+    scan_status: Option<ScanMessage>,
+    scan_errors: usize,
+    volume: VolumeJson,
+    sleep_timer: Option<SleepTimerJson>,
+    repeat_mode: RepeatMode,
+    playback_errors: usize,
+    last_playback_error: Option<PlaybackErrorJson>,
+    stream_title: Option<String>,
+    visualization_enabled: bool,
+    visualization: Option<VisualizationJson>,
+    browser_playback: bool,
 }
 
 enum AppMessage {
@@ -45,7 +63,7 @@ enum AppMessage {
     RefreshPlayStatus,
     RefreshPlayStatusDone((usize, GStreamerMessage)),
     RefreshList,
-    RefreshListDone(Vec<viola_common::Track>),
+    RefreshListDone(Vec<PlaylistItemJson>),
     RepeatOnce,
     LoadTabs,
     LoadTabsDone(PlaylistTabsJSON),
@@ -53,9 +71,24 @@ enum AppMessage {
     ToggleSidebar,
     ToggleDeleteRange,
     ShowFullPlaylist,
+    LoadVolume,
+    LoadVolumeDone(VolumeJson),
+    LoadRepeatMode,
+    LoadRepeatModeDone(RepeatMode),
+    ToggleVisualization,
+    ToggleBrowserPlayback,
+    BrowserPlayed(usize),
 }
 
 impl App {
+    fn increase_playcount(&mut self, i: usize) {
+        if self.current_tracks.get(i).is_some() {
+            let mut cloned_track = (*self.current_tracks[i]).clone();
+            cloned_track.playcount = Some(cloned_track.playcount.unwrap_or(0) + 1);
+            self.current_tracks[i] = Rc::new(cloned_track);
+        }
+    }
+
     fn handle_wsmessage(&mut self, ctx: &Context<Self>, msg: viola_common::WsMessage) -> bool {
         match msg {
             WsMessage::PlayChanged(i) => {
@@ -79,6 +112,52 @@ impl App {
                 false
             }
             WsMessage::Ping => false,
+            WsMessage::Scan(msg) => {
+                match msg {
+                    ScanMessage::Error(err) => {
+                        log::info!("Scan error: {}", err);
+                        self.scan_errors += 1;
+                    }
+                    ScanMessage::Started => {
+                        self.scan_errors = 0;
+                        self.scan_status = Some(msg);
+                    }
+                    _ => self.scan_status = Some(msg),
+                }
+                true
+            }
+            WsMessage::Mix(msg) => {
+                log::info!("{}", msg);
+                false
+            }
+            WsMessage::Visualization(visualization) => {
+                if self.visualization_enabled {
+                    self.visualization = Some(visualization);
+                }
+                self.visualization_enabled
+            }
+            WsMessage::VisualizationInterval(interval) => {
+                self.visualization_enabled = interval.is_some();
+                if interval.is_none() {
+                    self.visualization = None;
+                }
+                true
+            }
+            WsMessage::PlaybackError(error) => {
+                log::info!("Playback error: {:?}", error);
+                self.playback_errors += 1;
+                self.last_playback_error = Some(error);
+                true
+            }
+            WsMessage::StreamTitle(title) => {
+                self.stream_title = title;
+                true
+            }
+            WsMessage::SleepTimer(timer) => {
+                let changed = self.sleep_timer != timer;
+                self.sleep_timer = timer;
+                changed
+            }
             WsMessage::GStreamerMessage(msg) => match msg {
                 GStreamerMessage::Pausing
                 | GStreamerMessage::Stopped
@@ -92,22 +171,29 @@ impl App {
                     true
                 }
                 GStreamerMessage::IncreasePlayCount(i) => {
-                    if self.current_tracks.get(i).is_some() {
-                        let mut cloned_track = (*self.current_tracks[i]).clone();
-                        cloned_track.playcount = Some(cloned_track.playcount.unwrap_or(0) + 1);
-                        self.current_tracks[i] = Rc::new(cloned_track);
-                    }
+                    self.increase_playcount(i);
                     true
                 }
                 GStreamerMessage::Nop => false,
                 GStreamerMessage::ChangedDuration(_) => false,
-                GStreamerMessage::FileNotFound => {
-                    web_sys::window()
-                        .unwrap()
-                        .alert_with_message("Next File Not Found")
-                        .expect("Coud not send alert");
-                    false
+                GStreamerMessage::Seeked(position) => {
+                    self.current_track_time = position / 1_000_000;
+                    true
+                }
+                GStreamerMessage::RateChanged => false,
+                GStreamerMessage::PlayModeChanged(_) => false,
+                GStreamerMessage::RepeatChanged(mode) => {
+                    self.repeat_mode = mode;
+                    true
                 }
+                GStreamerMessage::VolumeChanged(volume) => {
+                    self.volume = volume;
+                    true
+                }
+                // the details come with WsMessage::PlaybackError
+                GStreamerMessage::FileNotFound | GStreamerMessage::PlaybackError => false,
+                // the title comes with WsMessage::StreamTitle
+                GStreamerMessage::StreamTitleChanged => false,
             },
         }
     }
@@ -141,6 +227,7 @@ impl Component for App {
             current_playing: 0,
             current_status: GStreamerMessage::Stopped,
             current_tracks: vec![],
+            missing_tracks: Rc::new(HashSet::new()),
             current_track_time: 0,
             repeat_once: false,
             sidebar_visible: false,
@@ -151,9 +238,27 @@ impl Component for App {
                 tabs: vec![],
             },
             show_full_playlist: false,
+            scan_status: None,
+            scan_errors: 0,
+            volume: VolumeJson {
+                volume: 0,
+                muted: false,
+            },
+            sleep_timer: None,
+            repeat_mode: RepeatMode::Off,
+            playback_errors: 0,
+            last_playback_error: None,
+            stream_title: None,
+            visualization_enabled: false,
+            visualization: None,
+            browser_playback: false,
         };
-        ctx.link()
-            .send_message_batch(vec![AppMessage::LoadTabs, AppMessage::RefreshList]);
+        ctx.link().send_message_batch(vec![
+            AppMessage::LoadTabs,
+            AppMessage::RefreshList,
+            AppMessage::LoadVolume,
+            AppMessage::LoadRepeatMode,
+        ]);
         a
     }
 
@@ -162,7 +267,7 @@ impl Component for App {
             AppMessage::WsMessage(msg) => self.handle_wsmessage(ctx, msg),
             AppMessage::RefreshList => {
                 ctx.link().send_future(async move {
-                    let new_tracks: Vec<viola_common::Track> = Request::get("/playlist/")
+                    let new_tracks: Vec<PlaylistItemJson> = Request::get("/playlist/")
                         .send()
                         .await
                         .unwrap()
@@ -174,8 +279,16 @@ impl Component for App {
                 ctx.link().send_message(AppMessage::RefreshPlayStatus);
                 false
             }
-            AppMessage::RefreshListDone(tracks) => {
-                self.current_tracks = tracks.into_iter().map(Rc::new).collect();
+            AppMessage::RefreshListDone(items) => {
+                self.missing_tracks = Rc::new(
+                    items
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, item)| item.missing)
+                        .map(|(index, _)| index)
+                        .collect(),
+                );
+                self.current_tracks = items.into_iter().map(|item| Rc::new(item.track)).collect();
                 true
             }
             AppMessage::RefreshPlayStatus => {
@@ -235,10 +348,79 @@ impl Component for App {
                 self.show_full_playlist = true;
                 false
             }
+            AppMessage::LoadVolume => {
+                ctx.link().send_future(async move {
+                    let volume: VolumeJson = Request::get("/volume/")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    AppMessage::LoadVolumeDone(volume)
+                });
+                false
+            }
+            AppMessage::LoadVolumeDone(volume) => {
+                self.volume = volume;
+                true
+            }
+            AppMessage::LoadRepeatMode => {
+                ctx.link().send_future(async move {
+                    let mode: RepeatMode = Request::get("/repeatmode/")
+                        .send()
+                        .await
+                        .unwrap()
+                        .json()
+                        .await
+                        .unwrap();
+                    AppMessage::LoadRepeatModeDone(mode)
+                });
+                false
+            }
+            AppMessage::LoadRepeatModeDone(mode) => {
+                self.repeat_mode = mode;
+                true
+            }
+            AppMessage::ToggleVisualization => {
+                self.visualization_enabled = !self.visualization_enabled;
+                self.visualization = None;
+                true
+            }
+            AppMessage::ToggleBrowserPlayback => {
+                self.browser_playback = !self.browser_playback;
+                // we do not want to hear both
+                if self.browser_playback && self.current_status == GStreamerMessage::Playing {
+                    spawn_local(async move {
+                        Request::post("/transport/")
+                            .header("Content-Type", "application/json")
+                            .body(serde_json::to_string(&GStreamerAction::Pausing).unwrap())
+                            .unwrap()
+                            .send()
+                            .await
+                            .unwrap();
+                    });
+                }
+                true
+            }
+            AppMessage::BrowserPlayed(i) => {
+                self.increase_playcount(i);
+                true
+            }
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
+        // the playing position is only in the shown tab if it is the one playing, the player restarts with the tab
+        let browser_start = if self
+            .playlist_tabs
+            .current_playing_in
+            .is_some_and(|s| s != self.playlist_tabs.current)
+        {
+            0
+        } else {
+            self.current_playing
+        };
         let full_time_playing: u64 = self.current_tracks.iter().map(|t| t.length as u64).sum();
         let remaining_time_playing: u64 = self
             .current_tracks
@@ -257,6 +439,41 @@ impl Component for App {
             })
             .cloned()
             .collect::<Vec<Rc<Track>>>();
+        let scan_status = if let Some(ref scan) = self.scan_status {
+            html! {
+                <div class="row" style="padding: 0.1em">
+                    <div class="col">{format!("{} ({} errors)", scan, self.scan_errors)}</div>
+                </div>
+            }
+        } else {
+            html! {}
+        };
+        let playback_error = if let Some(ref error) = self.last_playback_error {
+            html! {
+                <div class="row" style="padding: 0.1em">
+                    <div class="col">{format!(
+                        "Could not play {} ({} errors): {}",
+                        error.path.as_deref().unwrap_or("unknown file"),
+                        self.playback_errors,
+                        error.error
+                    )}</div>
+                </div>
+            }
+        } else {
+            html! {}
+        };
+        let sleep_timer = match self.sleep_timer {
+            Some(SleepTimerJson {
+                remaining: Some(remaining),
+                ..
+            }) => format!("Sleeping in {}", utils::format_time(remaining)),
+            Some(SleepTimerJson {
+                timer: SleepTimer::AfterAlbum,
+                ..
+            }) => String::from("Sleeping after this album"),
+            Some(_) => String::from("Sleeping after this track"),
+            None => String::new(),
+        };
         html! {
             <div class="container-fluid" style="padding-left: 5vw; padding-bottom: 1vh; height: 75vh">
                     <Sidebar
@@ -264,6 +481,7 @@ impl Component for App {
                         close_callback = {ctx.link().callback(|_| AppMessage::ToggleSidebar)}
                         reload_callback = {ctx.link().batch_callback(|_| vec![AppMessage::LoadTabs, AppMessage::RefreshList])}
                         show_all_tracks_callback = {ctx.link().callback(|_| AppMessage::ShowFullPlaylist)}
+                        current_tab = {self.playlist_tabs.current}
                         />
                     <DeleteRangeDialog
                         visible = {self.delete_range_visible}
@@ -276,11 +494,26 @@ impl Component for App {
                             <Buttons
                                 // the clean tab refresh will happen from the websocket and not here
                                 status={self.current_status}
+                                repeat_mode={self.repeat_mode}
                                 repeat_once_callback = {ctx.link().callback(|_| AppMessage::RepeatOnce)}
                                 refresh_play_callback = {ctx.link().callback(|_| AppMessage::RefreshPlayStatus)}
                                 sidebar_callback = {ctx.link().callback(|_| AppMessage::ToggleSidebar)}
                                 delete_range_callback = {ctx.link().callback(|_| AppMessage::ToggleDeleteRange)}
                                 />
+                            <Volume volume={self.volume} />
+                            <Meter
+                                enabled = {self.visualization_enabled}
+                                visualization = {self.visualization.clone()}
+                                toggle_callback = {ctx.link().callback(|_| AppMessage::ToggleVisualization)}
+                                />
+                            <BrowserPlayer
+                                key = {self.playlist_tabs.current}
+                                enabled = {self.browser_playback}
+                                tracks = {self.current_tracks.clone()}
+                                start = {browser_start}
+                                toggle_callback = {ctx.link().callback(|_| AppMessage::ToggleBrowserPlayback)}
+                                played_callback = {ctx.link().callback(AppMessage::BrowserPlayed)}
+                                />
 
                             <TabsComponent
                             // the tab refresh and similar thing will come from the websocket as otherwise we would refresh the old status
@@ -290,17 +523,23 @@ impl Component for App {
                             <div class="row" style="height: 75vh; width: 95vw; overflow-x: auto">
                                 <TracksComponent
                                     tracks={trimmed_tracks}
+                                    missing={self.missing_tracks.clone()}
                                     current_playing={self.current_playing}
                                     status = {self.current_status}
-                                    not_current_tab = {self.playlist_tabs.current_playing_in.map_or(false, |s| s!= self.playlist_tabs.current) }
+                                    not_current_tab = {self.playlist_tabs.current_playing_in.is_some_and(|s| s!= self.playlist_tabs.current) }
                                     />
                             </div>
 
                         <Status
                             current_status = {self.current_status}
-                            current_track = {self.current_tracks.get(self.current_playing).cloned()} total_track_time = {full_time_playing} remaining_time_playing = {remaining_time_playing} current_track_time={self.current_track_time} repeat_once = {self.repeat_once} number_of_tracks={self.current_tracks.len()}
+                            current_track = {self.current_tracks.get(self.current_playing).cloned()} stream_title = {self.stream_title.clone()} total_track_time = {full_time_playing} remaining_time_playing = {remaining_time_playing} current_track_time={self.current_track_time} repeat_once = {self.repeat_once} number_of_tracks={self.current_tracks.len()}
                             window = {TRACK_MAX_NUMBER}
                             />
+                        {scan_status}
+                        {playback_error}
+                        <div class="row" style="padding: 0.1em">
+                            <div class="col">{sleep_timer}</div>
+                        </div>
                         </div>
                     </div>
             </div>
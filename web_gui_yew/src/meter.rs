@@ -0,0 +1,90 @@
+use crate::button::*;
+use gloo_net::http::Request;
+use viola_common::VisualizationJson;
+use yew::prelude::*;
+
+/// milliseconds between the visualization data we ask for
+const INTERVAL: u64 = 50;
+/// the level we show as an empty meter in dB
+const LEVEL_FLOOR: f64 = -60.0;
+/// the magnitude we show as an empty band in dB, the server cuts the spectrum off there
+const SPECTRUM_FLOOR: f64 = -80.0;
+
+#[derive(Properties, PartialEq)]
+pub(crate) struct MeterProps {
+    pub(crate) enabled: bool,
+    pub(crate) visualization: Option<VisualizationJson>,
+    pub(crate) toggle_callback: Callback<()>,
+}
+
+/// how full a bar showing `db` is, in percent
+fn percent(db: i8, floor: f64) -> f64 {
+    ((f64::from(db) - floor) / -floor * 100.0).clamp(0.0, 100.0)
+}
+
+/// subscribes to the visualization data or unsubscribes with None
+fn subscribe(interval: Option<u64>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        Request::post("/visualization/")
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&interval).unwrap())
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+    });
+}
+
+#[function_component(Meter)]
+pub(crate) fn meter(props: &MeterProps) -> Html {
+    let enabled = props.enabled;
+    let toggle = props.toggle_callback.clone();
+    let toggle_callback = Callback::from(move |_| {
+        subscribe(if enabled { None } else { Some(INTERVAL) });
+        toggle.emit(());
+    });
+    let text = if enabled { "Hide Meter" } else { "Show Meter" };
+
+    let display = props.visualization.as_ref().map_or_else(
+        || html! {},
+        |vis| {
+            let levels = vis
+                .rms
+                .iter()
+                .zip(&vis.peak)
+                .map(|(rms, peak)| {
+                    html! {
+                        <div class="progress" style="height: 0.6em; margin-bottom: 0.1em" title={format!("Peak {} dB", peak)}>
+                            <div class="progress-bar" style={format!("width: {}%", percent(*rms, LEVEL_FLOOR))}></div>
+                        </div>
+                    }
+                })
+                .collect::<Html>();
+            let width = 100.0 / vis.bands.len().max(1) as f64;
+            let bands = vis
+                .bands
+                .iter()
+                .map(|band| {
+                    html! {
+                        <div class="bg-info" style={format!("width: {}%; height: {}%; margin-right: 1px", width, percent(*band, SPECTRUM_FLOOR))}></div>
+                    }
+                })
+                .collect::<Html>();
+            html! {
+                <>
+                    <div class="col-3">{levels}</div>
+                    <div class="col-3" style="display: flex; align-items: flex-end; height: 2.5em">{bands}</div>
+                </>
+            }
+        },
+    );
+
+    html! {
+        <div class="row" style="padding: 0.1em">
+            <div class="col-1">
+                <CallbackButton text={text} icon="/bar-chart.svg" btype={ButtonType::Secondary} callback={toggle_callback} />
+            </div>
+            {display}
+        </div>
+    }
+}
@@ -16,6 +16,8 @@ pub(crate) enum SidebarMsg {
     PlayDialogToggle,
     ShowFullPlaylistWindow,
     Save,
+    Scan,
+    CheckFiles,
 }
 
 struct TreeView {
@@ -37,6 +39,7 @@ pub(crate) struct SidebarProperties {
     pub(crate) close_callback: Callback<()>,
     pub(crate) reload_callback: Callback<()>,
     pub(crate) show_all_tracks_callback: Callback<()>,
+    pub(crate) current_tab: usize,
 }
 
 impl Component for Sidebar {
@@ -139,6 +142,31 @@ impl Component for Sidebar {
                 });
                 false
             }
+            SidebarMsg::Scan => {
+                ctx.link().send_future(async move {
+                    let resp = Request::post("/scan/").send().await.unwrap();
+                    if resp.status() == 409 {
+                        web_sys::window()
+                            .unwrap()
+                            .alert_with_message("A scan is already running")
+                            .expect("Coud not send alert");
+                    }
+                    SidebarMsg::Close
+                });
+                false
+            }
+            SidebarMsg::CheckFiles => {
+                let tab = ctx.props().current_tab;
+                ctx.link().send_future(async move {
+                    // the playlist reloads over the websocket if we found missing files
+                    Request::post(&format!("/validate/{}/", tab))
+                        .send()
+                        .await
+                        .unwrap();
+                    SidebarMsg::Close
+                });
+                false
+            }
             SidebarMsg::PlayDialogToggle => {
                 self.playdialog_visible = !self.playdialog_visible;
                 true
@@ -274,6 +302,22 @@ impl Component for Sidebar {
                                 callback = {ctx.link().callback(|_| SidebarMsg::Save)}
                                 />
                         </li>
+                        <li class="nav-item" style="padding: 5px">
+                            <CallbackButton
+                                text={"Scan Library"}
+                                icon={"/arrow-repeat.svg"}
+                                btype={ButtonType::Danger}
+                                callback = {ctx.link().callback(|_| SidebarMsg::Scan)}
+                                />
+                        </li>
+                        <li class="nav-item" style="padding: 5px">
+                            <CallbackButton
+                                text={"Check Tab For Missing Files"}
+                                icon={"/arrow-repeat.svg"}
+                                btype={ButtonType::Primary}
+                                callback = {ctx.link().callback(|_| SidebarMsg::CheckFiles)}
+                                />
+                        </li>
                     </ul>
                 </div>
             </>
@@ -11,6 +11,7 @@ pub(crate) struct StatusMsgProperties {
     pub(crate) number_of_tracks: usize,
     pub(crate) current_status: GStreamerMessage,
     pub(crate) current_track: Option<Rc<Track>>,
+    pub(crate) stream_title: Option<String>,
     pub(crate) current_track_time: u64,
     pub(crate) total_track_time: u64,
     pub(crate) remaining_time_playing: u64,
@@ -36,8 +37,11 @@ impl Component for Status {
                 format!("{}", ctx.props().number_of_tracks)
             };
             let status = ctx.props().current_status.to_string();
-            let track_status_string =
-                format!("{} - {} - {}", track.title, track.artist, track.album);
+            let track_status_string = match ctx.props().stream_title {
+                Some(ref title) if track.is_stream() => format!("{} - {}", title, track.title),
+                _ if track.is_stream() => track.title.clone(),
+                _ => format!("{} - {} - {}", track.title, track.artist, track.album),
+            };
             let total_time_string = format!(
                 "{} ({})",
                 utils::format_time(ctx.props().total_track_time),
@@ -48,14 +52,23 @@ impl Component for Status {
             } else {
                 ""
             };
-            let time_string = String::from("Time: ")
-                + &utils::format_time(ctx.props().current_track_time)
-                + "--"
-                + &utils::format_time(track.length as u64);
-            let track_percentage_width = format!(
-                "width: {}%",
-                ((ctx.props().current_track_time as f32 / track.length as f32) * 100.0).round()
-            );
+            // streams have no length
+            let time_string = if track.is_stream() {
+                String::from("Time: ") + &utils::format_time(ctx.props().current_track_time)
+            } else {
+                String::from("Time: ")
+                    + &utils::format_time(ctx.props().current_track_time)
+                    + "--"
+                    + &utils::format_time(track.length as u64)
+            };
+            let track_percentage_width = if track.is_stream() {
+                String::from("width: 0%")
+            } else {
+                format!(
+                    "width: {}%",
+                    ((ctx.props().current_track_time as f32 / track.length as f32) * 100.0).round()
+                )
+            };
             let cover_src = format!("/currentimage?nonce={}", track.id);
             html! {
                 <div class="row border border-dark" style="padding: 0.1em">
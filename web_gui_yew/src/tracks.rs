@@ -1,5 +1,5 @@
 use gloo_net::http::Request;
-use std::rc::Rc;
+use std::{collections::HashSet, rc::Rc};
 use viola_common::{GStreamerAction, GStreamerMessage};
 
 use yew::prelude::*;
@@ -14,6 +14,8 @@ pub(crate) enum TracksComponentMsg {
 #[derive(Properties, PartialEq)]
 pub(crate) struct TracksComponentProps {
     pub(crate) tracks: Vec<Rc<viola_common::Track>>,
+    /// positions of the tracks whose file is missing
+    pub(crate) missing: Rc<HashSet<usize>>,
     pub(crate) current_playing: usize,
     pub(crate) status: GStreamerMessage,
     pub(crate) not_current_tab: bool,
@@ -53,6 +55,8 @@ fn color_match(index: usize, ctx: &Context<TracksComponent>) -> (String, Html) {
             html! {
             <img src="/pause.svg" /> },
         )
+    } else if props.missing.contains(&index) {
+        (String::from("table-danger"), html! {})
     } else {
         (String::from(""), html! {})
     }
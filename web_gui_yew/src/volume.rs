@@ -0,0 +1,56 @@
+use crate::button::*;
+use gloo_net::http::Request;
+use viola_common::VolumeJson;
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub(crate) struct VolumeProps {
+    pub(crate) volume: VolumeJson,
+}
+
+/// sends the volume to the server, the new volume comes back over the websocket
+fn set_volume(volume: VolumeJson) {
+    wasm_bindgen_futures::spawn_local(async move {
+        Request::post("/volume/")
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&volume).unwrap())
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+    });
+}
+
+#[function_component(Volume)]
+pub(crate) fn volume(props: &VolumeProps) -> Html {
+    let current = props.volume;
+    let onchange = Callback::from(move |e: Event| {
+        let input: HtmlInputElement = e.target_unchecked_into();
+        if let Ok(volume) = input.value().parse::<u32>() {
+            set_volume(VolumeJson { volume, ..current });
+        }
+    });
+    let mute_callback = Callback::from(move |_| {
+        set_volume(VolumeJson {
+            muted: !current.muted,
+            ..current
+        });
+    });
+    let (text, icon) = if current.muted {
+        ("Unmute", "/volume-mute.svg")
+    } else {
+        ("Mute", "/volume-up.svg")
+    };
+    html! {
+        <div class="row" style="padding: 0.1em">
+            <div class="col-1">
+                <CallbackButton text={text} icon={icon} btype={ButtonType::Secondary} callback={mute_callback} />
+            </div>
+            <div class="col-3">
+                <input type="range" class="form-range" min="0" max="100" value={current.volume.to_string()} onchange={onchange} />
+            </div>
+            <div class="col-1">{format!("{}%", current.volume)}</div>
+        </div>
+    }
+}